@@ -1,5 +1,6 @@
 use schemars::schema::RootSchema;
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Profiles {
@@ -15,26 +16,649 @@ pub struct EnvConfig {
 pub struct Bootstrap {
     pub server: Server,
     pub services: Vec<Service>,
-    pub smtp: Smtp,
+    // 兼容早期配置 等同于notifiers中名为smtp的邮件渠道
+    pub smtp: Option<Smtp>,
+    #[serde(default)]
+    pub notifiers: Vec<NotifierConfig>,
+    // 节点健康判断规则 未配置时使用内置的默认规则
+    #[serde(default = "default_rules")]
+    pub rules: Vec<Rule>,
+    // 按主机名/网段/标签匹配的节点组阈值
+    #[serde(default)]
+    pub overrides: Vec<NodeOverride>,
+    #[serde(default)]
+    pub alerting: Alerting,
+    // 告警路由树 未配置时所有事件发送到全部通知渠道
+    pub route: Option<Route>,
+    // 周期性的维护窗口 窗口内照常判断健康状态但不发送通知
+    #[serde(default)]
+    pub maintenance: Vec<Maintenance>,
+    // 值班表与升级策略 由路由的escalation引用
+    #[serde(default)]
+    pub oncall: OnCall,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Server {
     pub addr: String,
+    // 对外访问本服务的地址 用于在通知中附带链接 如 http://monitor.domain.tld:3000
+    pub public_url: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Service {
     pub name: String,
     pub api: String,
+    #[serde(default)]
+    pub kind: CheckKind,
+    // 供告警路由等按标签匹配
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    // 响应正常但耗时(毫秒)达到阈值时告警
+    pub latency_warn_ms: Option<u128>,
+    pub latency_error_ms: Option<u128>,
+    #[serde(default = "default_method")]
+    pub method: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    // GET/HEAD请求不能带body
+    pub body: Option<String>,
+    // 不能与headers中的Authorization同时配置
+    pub auth: Option<Auth>,
+    // 须大于0
+    #[serde(default = "default_service_timeout_secs")]
+    pub timeout_secs: u64,
+    // 视为正常的状态码 可为 204、200-299 或 2xx
+    #[serde(default = "default_expect_status")]
+    pub expect_status: Vec<String>,
+    // 最多跟随的重定向次数 0表示不跟随
+    #[serde(default = "default_max_redirects")]
+    pub max_redirects: usize,
+    // 关闭后不校验证书 用于自签名证书
+    #[serde(default = "default_true")]
+    pub tls_verify: bool,
+    // 额外信任的CA证书(PEM)路径
+    pub ca_cert: Option<String>,
+    // 请求前单独建立一次连接 测量DNS解析、TCP连接与TLS握手的耗时
+    #[serde(default)]
+    pub probe_phases: bool,
+    // 状态码符合预期后对响应内容的断言 未通过时按各自的级别告警
+    #[serde(default)]
+    pub assertions: Vec<Assertion>,
+    // tcp检查: api为 host:port 连接后可发送payload并校验返回的banner
+    pub payload: Option<String>,
+    pub banner: Option<String>,
+    pub banner_regex: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Assertion {
+    #[serde(flatten)]
+    pub expect: Expect,
+    #[serde(default = "default_assertion_severity")]
+    pub severity: Severity,
+}
+
+fn default_assertion_severity() -> Severity {
+    Severity::Red
+}
+
+// 如 {kind: json_path, path: $.status, equals: UP}
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Expect {
+    Contains {
+        value: String,
+    },
+    Regex {
+        pattern: String,
+    },
+    // equals与op/value至少配置一项 op/value用于数值比较
+    JsonPath {
+        path: String,
+        equals: Option<serde_json::Value>,
+        op: Option<Comparison>,
+        value: Option<f64>,
+    },
+    // 响应体字节数的范围 超过max即停止读取 未配置时最多读取1MiB
+    Size {
+        min: Option<usize>,
+        max: Option<usize>,
+    },
+}
+
+// 服务检查的类型 actuator与health_json会解析响应中各依赖组件的状态
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckKind {
+    #[default]
+    Http,
+    // Spring Boot /actuator/health
+    Actuator,
+    // application/health+json (draft-inadarei-api-health-check)
+    HealthJson,
+    // 端口连通性
+    Tcp,
+}
+
+// 如 {kind: bearer, token: xxx}
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Auth {
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    Bearer {
+        token: String,
+    },
+}
+
+pub(crate) fn default_method() -> String {
+    "GET".to_string()
+}
+
+fn default_service_timeout_secs() -> u64 {
+    3
+}
+
+pub(crate) fn default_expect_status() -> Vec<String> {
+    vec!["200".to_string()]
 }
+
+pub(crate) fn default_max_redirects() -> usize {
+    10
+}
+
+fn default_true() -> bool {
+    true
+}
+// 通知渠道 name用于区分同类型的多个渠道
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NotifierConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub channel: Channel,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Channel {
+    Smtp(Smtp),
+    Webhook(Webhook),
+    Dingtalk(Robot),
+    Feishu(Robot),
+    Wecom(Robot),
+    Slack(ChatHook),
+    Teams(ChatHook),
+    Discord(ChatHook),
+    Pagerduty(Pagerduty),
+    Opsgenie(Opsgenie),
+}
+
+// PagerDuty Events API v2
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Pagerduty {
+    pub routing_key: String,
+    #[serde(default = "default_pagerduty_url")]
+    pub url: String,
+    #[serde(default = "default_http_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default = "default_http_retries")]
+    pub retries: u32,
+}
+
+// Opsgenie Alert API url为alerts接口地址 欧洲区为 https://api.eu.opsgenie.com/v2/alerts
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Opsgenie {
+    pub api_key: String,
+    #[serde(default = "default_opsgenie_url")]
+    pub url: String,
+    #[serde(default = "default_http_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default = "default_http_retries")]
+    pub retries: u32,
+}
+
+fn default_pagerduty_url() -> String {
+    "https://events.pagerduty.com/v2/enqueue".to_string()
+}
+
+fn default_opsgenie_url() -> String {
+    "https://api.opsgenie.com/v2/alerts".to_string()
+}
+
+// Slack/Teams/Discord的incoming webhook
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChatHook {
+    pub url: String,
+    #[serde(default = "default_http_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default = "default_http_retries")]
+    pub retries: u32,
+}
+
+// 钉钉/飞书/企业微信群机器人 url为完整的webhook地址
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Robot {
+    pub url: String,
+    // 钉钉/飞书的加签密钥
+    pub secret: Option<String>,
+    #[serde(default = "default_http_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default = "default_http_retries")]
+    pub retries: u32,
+}
+
+// 通用http回调
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Webhook {
+    pub url: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    // 请求体模板(handlebars) 未配置时发送json格式的HealthInfo列表
+    pub body_template: Option<String>,
+    // 配置后以HMAC-SHA256对请求体签名 写入signature_header
+    pub secret: Option<String>,
+    #[serde(default = "default_signature_header")]
+    pub signature_header: String,
+    #[serde(default = "default_http_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default = "default_http_retries")]
+    pub retries: u32,
+}
+
+fn default_signature_header() -> String {
+    "X-Signature-256".to_string()
+}
+
+fn default_http_timeout_secs() -> u64 {
+    5
+}
+
+fn default_http_retries() -> u32 {
+    3
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Smtp {
     pub from: String,
-    pub to: String,
-    pub username: String,
-    pub password: String,
+    // 收件人可以是单个地址或地址列表
+    #[serde(deserialize_with = "one_or_many")]
+    pub to: Vec<String>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub cc: Vec<String>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub bcc: Vec<String>,
+    // 未配置用户名时不做认证
+    pub username: Option<String>,
+    pub password: Option<String>,
     pub domain: String,
+    // 未配置时按tls模式取默认端口 none:25 starttls:587 implicit:465
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub tls: TlsMode,
+    // 邮件标题前缀 如 [prod]
+    #[serde(default)]
+    pub subject_prefix: String,
+    // 自定义邮件模板(handlebars)文件路径 未配置时使用内置模板
+    pub html_template: Option<String>,
+    pub text_template: Option<String>,
+    // 发件箱目录 每个渠道使用以渠道名命名的子目录
+    #[serde(default = "default_outbox_dir")]
+    pub outbox_dir: String,
+    // 投递失败的最大尝试次数 超过后移入dead目录
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    // 首次重试的间隔(秒) 之后每次翻倍
+    #[serde(default = "default_retry_backoff_secs")]
+    pub retry_backoff_secs: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TlsMode {
+    None,
+    #[default]
+    Starttls,
+    Implicit,
+}
+
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(one) => vec![one],
+        OneOrMany::Many(many) => many,
+    })
+}
+
+fn default_outbox_dir() -> String {
+    "outbox".to_string()
+}
+
+fn default_max_retries() -> u32 {
+    8
+}
+
+fn default_retry_backoff_secs() -> u64 {
+    30
+}
+
+// 告警通知相关配置
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Alerting {
+    // 告警持续期间重复通知的间隔(秒) 0表示只在状态变化时通知 作为根路由的repeat_interval
+    #[serde(default = "default_renotify_secs")]
+    pub renotify_secs: u64,
+    // 通过API创建的静默规则的保存位置
+    #[serde(default = "default_silences_file")]
+    pub silences_file: String,
+    // 确认后停止重复通知的时长(秒) 0表示直到告警恢复
+    #[serde(default)]
+    pub ack_timeout_secs: u64,
+    // 通知中一键确认链接的签名密钥 未配置时每次启动随机生成
+    pub ack_secret: Option<String>,
+    // 抖动检测 未配置时不检测
+    pub flapping: Option<Flapping>,
+}
+
+// 窗口内健康状态变化达到threshold次视为抖动 只通知一次
+// 降到recover_threshold次以下视为恢复稳定 按当时的状态重新通知
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Flapping {
+    #[serde(default = "default_flapping_window_secs")]
+    pub window_secs: u64,
+    #[serde(default = "default_flapping_threshold")]
+    pub threshold: usize,
+    #[serde(default = "default_flapping_recover_threshold")]
+    pub recover_threshold: usize,
+}
+
+fn default_flapping_window_secs() -> u64 {
+    1800
+}
+
+fn default_flapping_threshold() -> usize {
+    6
+}
+
+fn default_flapping_recover_threshold() -> usize {
+    2
+}
+
+impl Default for Alerting {
+    fn default() -> Self {
+        Alerting {
+            renotify_secs: default_renotify_secs(),
+            silences_file: default_silences_file(),
+            ack_timeout_secs: 0,
+            ack_secret: None,
+            flapping: None,
+        }
+    }
+}
+
+fn default_silences_file() -> String {
+    "silences.json".to_string()
+}
+
+fn default_renotify_secs() -> u64 {
+    4 * 3600
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    #[serde(alias = "yellow")]
+    Yellow,
+    #[serde(alias = "red")]
+    Red,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetKind {
+    #[serde(alias = "node")]
+    Node,
+    #[serde(alias = "service")]
+    Service,
+}
+
+// 告警的匹配条件 所有已配置的条件都满足才算匹配
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AlertMatcher {
+    pub severity: Option<Severity>,
+    pub kind: Option<TargetKind>,
+    // 节点id或服务名的glob 如 db-*
+    pub name: Option<String>,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+}
+
+// 维护窗口 cron与weekly二选一 时间均为本地时间
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Maintenance {
+    pub name: String,
+    #[serde(default, rename = "match")]
+    pub matcher: AlertMatcher,
+    // 窗口开始时间的cron表达式 如 "0 2 * * Sun" 需同时配置duration_secs
+    pub cron: Option<String>,
+    pub duration_secs: Option<u64>,
+    pub weekly: Option<Weekly>,
+}
+
+// 每周固定时段 end早于start时表示跨越午夜
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Weekly {
+    // 如 [Sat, Sun]
+    pub days: Vec<String>,
+    // HH:MM
+    pub start: String,
+    pub end: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct OnCall {
+    #[serde(default)]
+    pub schedules: Vec<Schedule>,
+    #[serde(default)]
+    pub escalations: Vec<Escalation>,
+}
+
+// 轮值表 从start起receivers按顺序每handoff_days天交接一次 时间均为本地时间
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Schedule {
+    pub name: String,
+    // 通知渠道名
+    pub receivers: Vec<String>,
+    // 第一次交接的时间 YYYY-MM-DD HH:MM 此时轮到receivers[0]
+    pub start: String,
+    #[serde(default = "default_handoff_days")]
+    pub handoff_days: u64,
+    // 临时换班 时段内由receiver代替轮值的人
+    #[serde(default)]
+    pub overrides: Vec<ScheduleOverride>,
+}
+
+fn default_handoff_days() -> u64 {
+    7
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScheduleOverride {
+    pub receiver: String,
+    // YYYY-MM-DD HH:MM
+    pub start: String,
+    pub end: String,
+}
+
+// 升级策略 告警firing后按各级的延迟逐级通知 确认后停止升级
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Escalation {
+    pub name: String,
+    pub steps: Vec<EscalationStep>,
+}
+
+// schedule与receiver二选一
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EscalationStep {
+    // 距告警开始通知的秒数 须不小于上一级 确认或静默结束后按与上一级的间隔重新计时
+    #[serde(default)]
+    pub delay_secs: u64,
+    pub schedule: Option<String>,
+    pub receiver: Option<String>,
+}
+
+// 告警路由 子路由按顺序匹配 命中的子路由未设置continue时停止匹配后续子路由
+// 没有子路由命中时由当前路由发送
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Route {
+    #[serde(default, rename = "match")]
+    pub matcher: AlertMatcher,
+    // 通知渠道名 未配置时继承上级路由 根路由未配置时发送到全部渠道
+    pub receiver: Option<String>,
+    // firing期间重复通知的间隔(秒) 未配置时继承上级路由 根路由默认为alerting.renotify_secs
+    pub repeat_interval: Option<u64>,
+    // 分组依据 可为kind/severity/name或标签名 未配置时继承上级路由 根路由默认所有告警为一组
+    pub group_by: Option<Vec<String>>,
+    // 新分组等待多少秒再发送 以收集同组的其他告警
+    pub group_wait: Option<u64>,
+    // 同一分组两次发送之间至少间隔的秒数
+    pub group_interval: Option<u64>,
+    // 升级策略名 配置后命中此路由的告警按策略逐级通知 不再经由receiver分组发送 未配置时继承上级路由
+    pub escalation: Option<String>,
+    #[serde(default, rename = "continue")]
+    pub continue_matching: bool,
+    #[serde(default)]
+    pub routes: Vec<Route>,
+}
+
+// 规则可判断的节点指标
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Metric {
+    #[serde(rename = "disk_per")]
+    DiskPer,
+    #[serde(rename = "mem_status_per")]
+    MemStatusPer,
+    #[serde(rename = "load_1")]
+    Load1,
+    #[serde(rename = "load_5")]
+    Load5,
+    #[serde(rename = "load_15")]
+    Load15,
+    // 距离节点上次上报经过的秒数
+    #[serde(rename = "stale_secs")]
+    StaleSecs,
+}
+
+impl Metric {
+    // 与配置中的写法一致
+    pub fn name(self) -> &'static str {
+        match self {
+            Metric::DiskPer => "disk_per",
+            Metric::MemStatusPer => "mem_status_per",
+            Metric::Load1 => "load_1",
+            Metric::Load5 => "load_5",
+            Metric::Load15 => "load_15",
+            Metric::StaleSecs => "stale_secs",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Comparison {
+    Gt,
+    #[default]
+    Ge,
+    Lt,
+    Le,
+}
+
+// 单条阈值规则 消息模板支持 {metric} {value} {threshold} 占位符
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Rule {
+    pub metric: Metric,
+    #[serde(default)]
+    pub op: Comparison,
+    pub warn: Option<f64>,
+    pub error: Option<f64>,
+    pub warn_msg: Option<String>,
+    pub error_msg: Option<String>,
+    // 条件需持续的秒数及连续采样次数 都满足才会触发
+    pub for_secs: Option<u64>,
+    pub for_samples: Option<u32>,
+    // 恢复阈值 触发后需越过该值才会解除 未配置时与触发阈值相同
+    pub warn_recover: Option<f64>,
+    pub error_recover: Option<f64>,
+}
+
+// 节点匹配条件 所有已配置的条件都满足才算匹配
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct NodeMatcher {
+    // 主机名glob 如 db-*
+    pub hostname: Option<String>,
+    // IP网段 如 10.0.2.0/24
+    pub cidr: Option<String>,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+}
+
+// 节点组阈值 同一指标的规则会替换全局规则
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NodeOverride {
+    pub name: String,
+    #[serde(rename = "match")]
+    pub matcher: NodeMatcher,
+    pub rules: Vec<Rule>,
+}
+
+// 与早期硬编码阈值一致的默认规则
+pub fn default_rules() -> Vec<Rule> {
+    vec![
+        Rule {
+            metric: Metric::DiskPer,
+            op: Comparison::Ge,
+            warn: Some(70.0),
+            error: Some(90.0),
+            warn_msg: Some("Warn: disk > {threshold}% .".to_string()),
+            error_msg: Some("Error: disk > {threshold}% .".to_string()),
+            for_secs: None,
+            for_samples: None,
+            warn_recover: None,
+            error_recover: None,
+        },
+        Rule {
+            metric: Metric::MemStatusPer,
+            op: Comparison::Ge,
+            warn: Some(70.0),
+            error: Some(90.0),
+            warn_msg: Some("Warn: mem > {threshold}% .".to_string()),
+            error_msg: Some("Error: mem > {threshold}% .".to_string()),
+            for_secs: None,
+            for_samples: None,
+            warn_recover: None,
+            error_recover: None,
+        },
+        Rule {
+            metric: Metric::StaleSecs,
+            op: Comparison::Gt,
+            warn: Some(600.0),
+            error: Some(1200.0),
+            warn_msg: Some("Warn: node hasn't update for 10 min.".to_string()),
+            error_msg: Some("Error: node hasn't update for 20 min.".to_string()),
+            for_secs: None,
+            for_samples: None,
+            warn_recover: None,
+            error_recover: None,
+        },
+    ]
 }
 
 // 加载指定配置文件
@@ -45,14 +669,14 @@ where
     // 1.通过std::fs读取配置文件内容
     // 2.通过serde_yaml解析读取到的yaml配置转换成json对象
     match serde_yaml::from_str::<RootSchema>(
-        &std::fs::read_to_string(path).expect(&format!("failure read file {}", path)),
+        &std::fs::read_to_string(path).unwrap_or_else(|_| panic!("failure read file {}", path)),
     ) {
         Ok(root_schema) => {
             // 通过serde_json把json对象转换指定的model
             let data =
                 serde_json::to_string_pretty(&root_schema).expect("failure to parse RootSchema");
-            let config = serde_json::from_str::<T>(&*data)
-                .expect(&format!("failure to format json str {}", &data));
+            let config = serde_json::from_str::<T>(&data)
+                .unwrap_or_else(|_| panic!("failure to format json str {}", &data));
             // 返回格式化结果
             Some(config)
         }
@@ -0,0 +1,59 @@
+use crate::core::webhook::hmac_sha256;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use uuid::Uuid;
+
+// 通知中一键确认链接的签名与校验 签名只针对告警id 告警恢复后链接自然失效
+#[derive(Debug, Clone, Default)]
+pub struct AckSigner {
+    public_url: Option<String>,
+    secret: String,
+}
+
+impl AckSigner {
+    pub fn new(public_url: Option<String>, secret: Option<String>) -> AckSigner {
+        AckSigner {
+            public_url: public_url.map(|u| u.trim_end_matches('/').to_string()),
+            secret: secret.unwrap_or_else(|| Uuid::new_v4().to_string()),
+        }
+    }
+    fn sign(&self, id: &str) -> String {
+        hex::encode(hmac_sha256(self.secret.as_bytes(), id.as_bytes()))
+    }
+    // 未配置对外地址时不生成链接
+    pub fn link(&self, id: &str) -> Option<String> {
+        self.public_url
+            .as_ref()
+            .map(|base| format!("{}/alerts/{}/ack?sig={}", base, id, self.sign(id)))
+    }
+    // 常量时间比较
+    pub fn verify(&self, id: &str, sig: &str) -> bool {
+        let Ok(sig) = hex::decode(sig) else {
+            return false;
+        };
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes())
+            .expect("hmac accepts any key size");
+        mac.update(id.as_bytes());
+        mac.verify_slice(&sig).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_are_signed_per_alert() {
+        let signer = AckSigner::new(
+            Some("http://monitor:3000/".to_string()),
+            Some("s3cret".to_string()),
+        );
+        let link = signer.link("a1").unwrap();
+        assert!(link.starts_with("http://monitor:3000/alerts/a1/ack?sig="));
+        let sig = link.rsplit('=').next().unwrap();
+        assert!(signer.verify("a1", sig));
+        assert!(!signer.verify("a2", sig));
+        assert!(!signer.verify("a1", "zz"));
+        assert!(AckSigner::new(None, None).link("a1").is_none());
+    }
+}
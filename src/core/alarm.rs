@@ -1,63 +1,273 @@
 use super::ent;
-use chrono::Utc;
-use lettre::message::header::ContentType;
+use super::notifier::Notifier;
+use super::outbox::{Letter, Outbox, MAX_BACKOFF_SECS};
+use super::render::RenderContext;
+use crate::config::model::{Smtp, TlsMode};
+use handlebars::Handlebars;
+use lettre::message::{header::ContentType, Mailbox, MultiPart};
 use lettre::transport::smtp::authentication::Credentials;
-use lettre::{Message, SmtpTransport, Transport};
-use serde::Serialize;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::{path::Path, sync::Arc, time::Duration};
+use tokio::{sync::Notify, time};
+use uuid::Uuid;
 
-#[derive(Serialize)]
-struct EmailBody {
-    update_time: String,
-    events: Vec<ent::HealthInfo>,
+const HTML_TEMPLATE: &str = include_str!("templates/alert.html.hbs");
+const TEXT_TEMPLATE: &str = include_str!("templates/alert.txt.hbs");
+
+// html与纯文本两份模板 纯文本不做html转义
+struct Templates {
+    html: Handlebars<'static>,
+    text: Handlebars<'static>,
+}
+
+impl Templates {
+    fn load(html: &Option<String>, text: &Option<String>) -> Result<Templates, String> {
+        Ok(Templates {
+            html: load_template(html, HTML_TEMPLATE, true)?,
+            text: load_template(text, TEXT_TEMPLATE, false)?,
+        })
+    }
+}
+
+fn load_template(
+    path: &Option<String>,
+    builtin: &str,
+    escape: bool,
+) -> Result<Handlebars<'static>, String> {
+    let source = match path {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|e| format!("read template {} fail: {}", path, e))?,
+        None => builtin.to_string(),
+    };
+    let mut hb = Handlebars::new();
+    if !escape {
+        hb.register_escape_fn(handlebars::no_escape);
+    }
+    hb.register_template_string("mail", source)
+        .map_err(|e| format!("bad template {:?}: {}", path, e))?;
+    Ok(hb)
 }
 
+// 邮件通知渠道 notify只负责写入发件箱 由后台任务异步投递
 pub struct Alarm {
-    from: String,
-    to: String,
-    mailer: SmtpTransport,
+    name: String,
+    subject_prefix: String,
+    templates: Templates,
+    outbox: Arc<Outbox>,
+    wake: Arc<Notify>,
 }
 impl Alarm {
-    pub fn new(
-        from: String,
-        to: String,
-        smtp_username: String,
-        smtp_password: String,
-        domain: String,
-    ) -> Alarm {
-        let creds = Credentials::new(smtp_username, smtp_password);
-        Alarm {
+    // 需在tokio运行时内调用 会启动该渠道的投递任务
+    // 地址或tls配置有误时返回错误 以便启动时就能发现
+    pub fn new(name: String, config: Smtp) -> Result<Alarm, String> {
+        let mut errors = Vec::new();
+        let from = parse_mailboxes("from", &[config.from], &mut errors).pop();
+        let to = parse_mailboxes("to", &config.to, &mut errors);
+        let cc = parse_mailboxes("cc", &config.cc, &mut errors);
+        let bcc = parse_mailboxes("bcc", &config.bcc, &mut errors);
+        if to.is_empty() && cc.is_empty() && bcc.is_empty() {
+            errors.push("no recipient".to_string());
+        }
+        let templates = Templates::load(&config.html_template, &config.text_template)
+            .map_err(|e| errors.push(e))
+            .ok();
+        let builder = match config.tls {
+            TlsMode::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+                &config.domain,
+            )
+            .port(25)),
+            TlsMode::Starttls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.domain)
+                    .map(|b| b.port(587))
+            }
+            TlsMode::Implicit => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.domain),
+        };
+        if let Err(e) = &builder {
+            errors.push(format!("tls: {}", e));
+        }
+        let (Some(from), Some(templates), Ok(mut builder), true) =
+            (from, templates, builder, errors.is_empty())
+        else {
+            return Err(format!("smtp {}: {}", name, errors.join("; ")));
+        };
+        if let Some(port) = config.port {
+            builder = builder.port(port);
+        }
+        if let Some(username) = config.username {
+            builder = builder.credentials(Credentials::new(
+                username,
+                config.password.unwrap_or_default(),
+            ));
+        }
+        let outbox = Arc::new(
+            Outbox::open(Path::new(&config.outbox_dir).join(&name))
+                .map_err(|e| format!("smtp {}: open outbox fail {:?}", name, e))?,
+        );
+        let wake = Arc::new(Notify::new());
+        let courier = Courier {
             from,
             to,
-            mailer: SmtpTransport::starttls_relay(&domain)
-                .unwrap()
-                .port(587)
-                .credentials(creds)
-                .build(),
-        }
+            cc,
+            bcc,
+            mailer: builder.build(),
+            outbox: outbox.clone(),
+            wake: wake.clone(),
+            max_retries: config.max_retries,
+            backoff_secs: config.retry_backoff_secs,
+        };
+        tokio::spawn(courier.run());
+        Ok(Alarm {
+            name,
+            subject_prefix: config.subject_prefix,
+            templates,
+            outbox,
+            wake,
+        })
+    }
+}
+
+// 解析地址 错误信息收集到errors中
+fn parse_mailboxes(field: &str, addrs: &[String], errors: &mut Vec<String>) -> Vec<Mailbox> {
+    addrs
+        .iter()
+        .filter_map(|addr| match addr.parse::<Mailbox>() {
+            Ok(mailbox) => Some(mailbox),
+            Err(e) => {
+                errors.push(format!("invalid {} address {:?}: {}", field, addr, e));
+                None
+            }
+        })
+        .collect()
+}
+impl Notifier for Alarm {
+    fn name(&self) -> &str {
+        &self.name
     }
-    pub fn notify(&self, events: Vec<ent::HealthInfo>) {
-        let body = EmailBody {
-            update_time: Utc::now().to_rfc3339(),
-            events,
+    fn notify(&self, events: Vec<ent::HealthInfo>) {
+        let body = RenderContext::new(&events);
+        // 模板渲染失败时退回到原始的json内容
+        let text = self
+            .templates
+            .text
+            .render("mail", &body)
+            .unwrap_or_else(|e| {
+                tracing::error!("render text mail fail: {e}");
+                serde_json::to_string_pretty(&events).unwrap()
+            });
+        let html = self
+            .templates
+            .html
+            .render("mail", &body)
+            .map_err(|e| tracing::error!("render html mail fail: {e}"))
+            .ok();
+        let now = ent::timestamp();
+        let letter = Letter {
+            id: Uuid::new_v4().to_string(),
+            subject: format!("{}资源监控预警", self.subject_prefix),
+            body: text,
+            html,
+            created_at: now,
+            attempts: 0,
+            next_attempt: now,
+            last_error: None,
         };
-        let msg = serde_json::to_string_pretty(&body).unwrap();
-        let email = Message::builder()
-            .from(self.from.parse().unwrap())
-            .to(self.to.parse().unwrap())
-            .subject("资源监控预警")
-            .header(ContentType::TEXT_PLAIN)
-            .body(String::from(&msg))
-            .unwrap();
-        // Send the email
-        match self.mailer.send(&email) {
-            Ok(_) => tracing::info!("Email sent successfully!"),
+        match self.outbox.save(&letter) {
+            Ok(_) => self.wake.notify_one(),
             Err(e) => {
-                tracing::error!("Could not send email: {e:?}");
-                tracing::info!("Unsend mail: {:?}", &msg);
+                tracing::error!("Could not save email to outbox: {e:?}");
+                tracing::info!("Unsend mail: {:?}", &letter.body);
             }
         };
     }
 }
+
+// 发件箱的投递任务
+struct Courier {
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    cc: Vec<Mailbox>,
+    bcc: Vec<Mailbox>,
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+    outbox: Arc<Outbox>,
+    wake: Arc<Notify>,
+    max_retries: u32,
+    backoff_secs: u64,
+}
+
+impl Courier {
+    async fn run(self) {
+        loop {
+            let now = ent::timestamp();
+            let mut next_due = None;
+            for letter in self.outbox.pending() {
+                if letter.next_attempt > now {
+                    next_due = Some(
+                        next_due.map_or(letter.next_attempt, |t: u64| t.min(letter.next_attempt)),
+                    );
+                    continue;
+                }
+                self.deliver(letter).await;
+            }
+            // 等到下一封信的重试时间 或有新信写入
+            let wait = next_due.map_or(MAX_BACKOFF_SECS, |t| t.saturating_sub(ent::timestamp()));
+            tokio::select! {
+                _ = self.wake.notified() => {},
+                _ = time::sleep(Duration::from_secs(wait.max(1))) => {},
+            }
+        }
+    }
+    async fn deliver(&self, mut letter: Letter) {
+        letter.attempts += 1;
+        match self.send(&letter).await {
+            Ok(_) => {
+                tracing::info!("Email {} sent successfully!", letter.id);
+                letter.last_error = None;
+                self.outbox.record(&letter, "sent");
+                if let Err(e) = self.outbox.remove(&letter.id) {
+                    tracing::error!("outbox: remove letter {} fail {:?}", letter.id, e);
+                }
+            }
+            Err(e) => {
+                tracing::error!("Could not send email {}: {e}", letter.id);
+                let now = ent::timestamp();
+                if let Err(e) =
+                    self.outbox
+                        .fail(&mut letter, e, self.max_retries, self.backoff_secs, now)
+                {
+                    tracing::error!("outbox: save letter {} fail {:?}", letter.id, e);
+                }
+            }
+        }
+    }
+    async fn send(&self, letter: &Letter) -> Result<(), String> {
+        let mut builder = Message::builder().from(self.from.clone());
+        for mailbox in &self.to {
+            builder = builder.to(mailbox.clone());
+        }
+        for mailbox in &self.cc {
+            builder = builder.cc(mailbox.clone());
+        }
+        for mailbox in &self.bcc {
+            builder = builder.bcc(mailbox.clone());
+        }
+        let builder = builder.subject(&letter.subject);
+        let email = match &letter.html {
+            Some(html) => builder.multipart(MultiPart::alternative_plain_html(
+                letter.body.clone(),
+                html.clone(),
+            )),
+            None => builder
+                .header(ContentType::TEXT_PLAIN)
+                .body(letter.body.clone()),
+        }
+        .map_err(|e| e.to_string())?;
+        self.mailer
+            .send(email)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
 #[cfg(test)]
 mod tests {
     use lettre::message::header::ContentType;
@@ -89,4 +299,49 @@ mod tests {
             Err(e) => panic!("Could not send email: {e:?}"),
         };
     }
+
+    #[test]
+    fn invalid_addresses_fail_at_startup() {
+        let config: crate::config::model::Smtp = serde_json::from_value(serde_json::json!({
+            "from": "NoBody <nobody@domain.tld>",
+            "to": ["ops@domain.tld", "not an address"],
+            "bcc": "also@bad@domain",
+            "domain": "localhost",
+            "tls": "none",
+        }))
+        .unwrap();
+        let err = super::Alarm::new("test".to_string(), config).err().unwrap();
+        assert!(err.contains("invalid to address \"not an address\""));
+        assert!(err.contains("invalid bcc address"));
+    }
+
+    #[test]
+    fn builtin_templates_render_status_table() {
+        use crate::core::ent::*;
+        let events = vec![HealthInfo {
+            target: Target::Service(
+                "api".to_string(),
+                Some(Service {
+                    name: "api".to_string(),
+                    api: "http://api/health".to_string(),
+                    latency: 12,
+                    timings: Timings::default(),
+                    components: Vec::new(),
+                    last_updated: 0,
+                    status_msg: Some("Error: <timeout>".to_string()),
+                    labels: Default::default(),
+                }),
+            ),
+            status: HealthStatus::Red,
+            alert: None,
+        }];
+        let templates = super::Templates::load(&None, &None).unwrap();
+        let body = crate::core::render::RenderContext::new(&events);
+        let html = templates.html.render("mail", &body).unwrap();
+        assert!(html.contains("background: #d9534f"));
+        assert!(html.contains("Error: &lt;timeout&gt;"));
+        let text = templates.text.render("mail", &body).unwrap();
+        assert!(text.contains("[Red] service api"));
+        assert!(text.contains("Error: <timeout>"));
+    }
 }
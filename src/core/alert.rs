@@ -0,0 +1,556 @@
+use crate::config::model::Flapping;
+use crate::core::ack::AckSigner;
+use crate::core::ent::*;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, RwLock},
+};
+use uuid::Uuid;
+
+// 保留最近的状态变化条数
+const HISTORY_LIMIT: usize = 1000;
+
+// 一次告警状态变化 None表示inactive(未跟踪)
+#[derive(Debug, Clone, Serialize)]
+pub struct Transition {
+    pub at: u64,
+    pub from: Option<AlertState>,
+    pub to: Option<AlertState>,
+    pub msg: String,
+    // 告警的级别 恢复时为恢复前的级别
+    pub severity: HealthStatus,
+    // 变化时目标的健康信息
+    pub info: HealthInfo,
+}
+
+// 目标健康状态最近的变化时间 用于抖动检测
+#[derive(Default)]
+struct Flap {
+    last: Option<HealthStatus>,
+    // 变化的时间与变化后的状态
+    changes: VecDeque<(u64, HealthStatus)>,
+    flapping: bool,
+}
+
+impl Flap {
+    // 窗口内出现过的最严重的状态
+    fn worst(&self) -> Option<HealthStatus> {
+        self.changes
+            .iter()
+            .map(|(_, s)| *s)
+            .chain(self.last)
+            .min_by_key(|s| match s {
+                HealthStatus::Red => 0,
+                HealthStatus::Yellow => 1,
+                HealthStatus::Green => 2,
+            })
+    }
+}
+
+// Logger与API共享的告警状态
+pub type Alerts = Arc<RwLock<AlertBook>>;
+
+// 按目标记录告警状态 inactive -> pending -> firing -> resolved
+// 状态频繁变化时转为flapping 稳定后再转为firing或resolved
+// 只有状态变化才产生需要通知的事件 重复通知由Dispatcher按路由处理
+#[derive(Default)]
+pub struct AlertBook {
+    // 处于pending/firing的目标最近一次的健康信息
+    active: HashMap<String, HealthInfo>,
+    signer: AckSigner,
+    // 确认的有效期 0表示直到恢复
+    ack_timeout_secs: u64,
+    // 最近的状态变化 按时间先后
+    history: VecDeque<Transition>,
+    flapping: Option<Flapping>,
+    flaps: HashMap<String, Flap>,
+}
+
+impl AlertBook {
+    pub fn new(signer: AckSigner, ack_timeout_secs: u64, flapping: Option<Flapping>) -> AlertBook {
+        AlertBook {
+            active: HashMap::new(),
+            signer,
+            ack_timeout_secs,
+            history: VecDeque::new(),
+            flapping,
+            flaps: HashMap::new(),
+        }
+    }
+    // rule为本次命中的规则 pending表示本次采样已超限 但还未满足规则的持续条件
+    pub fn update(
+        &mut self,
+        health: HealthInfo,
+        rule: &str,
+        pending: bool,
+        now: u64,
+    ) -> Option<HealthInfo> {
+        let key = health.target.key();
+        let from = self.state(&key);
+        let last = self.active.get(&key).map(|h| h.status);
+        let event = match self.flap(&key, health.status, now) {
+            Some(true) => Some(self.start_flapping(health.clone(), rule, now)),
+            Some(false) => self.stop_flapping(health.clone(), rule, pending, now),
+            // 抖动期间只更新健康信息 不通知
+            None if from == Some(AlertState::Flapping) => {
+                if let Some(prev) = self.active.get_mut(&key) {
+                    let alert = prev.alert.take();
+                    *prev = HealthInfo {
+                        alert,
+                        ..health.clone()
+                    };
+                }
+                None
+            }
+            None => self.apply(health.clone(), rule, pending, now),
+        };
+        // 恢复的同时可能再次进入pending 以恢复为准
+        let to = match &event {
+            Some(e) => e.alert.as_ref().map(|a| a.state),
+            None => self.state(&key),
+        };
+        if event.is_some() || from != to {
+            let info = event
+                .clone()
+                .or_else(|| self.active.get(&key).cloned())
+                .unwrap_or(health);
+            let severity = match (info.status, last) {
+                (HealthStatus::Green, Some(last)) => last,
+                (status, _) => status,
+            };
+            self.record(from, to, severity, info, now);
+        }
+        event
+    }
+    fn state(&self, key: &str) -> Option<AlertState> {
+        self.active
+            .get(key)
+            .and_then(|h| h.alert.as_ref())
+            .map(|a| a.state)
+    }
+    fn record(
+        &mut self,
+        from: Option<AlertState>,
+        to: Option<AlertState>,
+        severity: HealthStatus,
+        info: HealthInfo,
+        at: u64,
+    ) {
+        if self.history.len() == HISTORY_LIMIT {
+            self.history.pop_front();
+        }
+        self.history.push_back(Transition {
+            at,
+            from,
+            to,
+            msg: info.msg().to_string(),
+            severity,
+            info,
+        });
+    }
+    // 记录健康状态的变化 返回Some(true)表示开始抖动 Some(false)表示恢复稳定
+    fn flap(&mut self, key: &str, status: HealthStatus, now: u64) -> Option<bool> {
+        let config = self.flapping.as_ref()?;
+        let flap = self.flaps.entry(key.to_string()).or_default();
+        if flap.last.is_some_and(|last| last != status) {
+            flap.changes.push_back((now, status));
+        }
+        flap.last = Some(status);
+        while flap
+            .changes
+            .front()
+            .is_some_and(|(t, _)| now.saturating_sub(*t) >= config.window_secs)
+        {
+            flap.changes.pop_front();
+        }
+        let changes = flap.changes.len();
+        if !flap.flapping && changes >= config.threshold {
+            flap.flapping = true;
+            return Some(true);
+        }
+        if flap.flapping && changes < config.recover_threshold {
+            flap.flapping = false;
+            return Some(false);
+        }
+        None
+    }
+    // 正在firing的告警沿用原有的告警id
+    // 开始抖动时的采样多为恢复 以窗口内最严重的状态通知 按级别的路由才能匹配
+    fn start_flapping(&mut self, mut health: HealthInfo, rule: &str, now: u64) -> HealthInfo {
+        let key = health.target.key();
+        if let Some(worst) = self.flaps.get(&key).and_then(Flap::worst) {
+            health.status = worst;
+        }
+        let alert = self
+            .active
+            .remove(&key)
+            .and_then(|h| h.alert)
+            .filter(|a| a.state == AlertState::Firing)
+            .unwrap_or_else(|| self.alert(rule, AlertState::Firing, now));
+        health.alert = Some(Alert {
+            state: AlertState::Flapping,
+            since: now,
+            last_notified: Some(now),
+            ..alert
+        });
+        self.active.insert(key, health.clone());
+        health
+    }
+    // 按当前状态转为firing或resolved 仍未恢复时同样通知
+    fn stop_flapping(
+        &mut self,
+        health: HealthInfo,
+        rule: &str,
+        pending: bool,
+        now: u64,
+    ) -> Option<HealthInfo> {
+        let key = health.target.key();
+        if let Some(alert) = self.active.get_mut(&key).and_then(|h| h.alert.as_mut()) {
+            alert.state = AlertState::Firing;
+            alert.since = now;
+        }
+        let event = self.apply(health, rule, pending, now);
+        event.or_else(|| {
+            let info = self.active.get_mut(&key)?;
+            let alert = info.alert.as_mut()?;
+            if alert.state != AlertState::Firing {
+                return None;
+            }
+            alert.last_notified = Some(now);
+            Some(info.clone())
+        })
+    }
+    fn alert(&self, rule: &str, state: AlertState, now: u64) -> Alert {
+        let id = Uuid::new_v4().to_string();
+        Alert {
+            // pending的告警不通知 无需确认
+            ack_url: (state != AlertState::Pending)
+                .then(|| self.signer.link(&id))
+                .flatten(),
+            id,
+            rule: rule.to_string(),
+            state,
+            since: now,
+            last_notified: None,
+            acked_by: None,
+            acked_at: None,
+        }
+    }
+    fn apply(
+        &mut self,
+        mut health: HealthInfo,
+        rule: &str,
+        pending: bool,
+        now: u64,
+    ) -> Option<HealthInfo> {
+        let key = health.target.key();
+        let prev = self.active.remove(&key);
+        let prev_alert = prev.as_ref().and_then(|h| h.alert.clone());
+        let was_firing = prev_alert
+            .as_ref()
+            .is_some_and(|a| a.state == AlertState::Firing);
+        if health.status != HealthStatus::Green {
+            let (mut alert, notify) = match prev_alert.filter(|_| was_firing) {
+                // 级别变化立即通知
+                Some(alert) => {
+                    let changed = prev.is_some_and(|p| p.status != health.status);
+                    (alert, changed)
+                }
+                None => (self.alert(rule, AlertState::Firing, now), true),
+            };
+            if notify {
+                alert.last_notified = Some(now);
+            }
+            health.alert = Some(alert);
+            self.active.insert(key, health.clone());
+            return notify.then_some(health);
+        }
+        let mut resolved = None;
+        if was_firing {
+            let mut info = health.clone();
+            info.alert = prev_alert.map(|a| Alert {
+                state: AlertState::Resolved,
+                since: now,
+                last_notified: Some(now),
+                ack_url: None,
+                ..a
+            });
+            resolved = Some(info);
+        }
+        if pending {
+            let since = prev
+                .and_then(|p| p.alert)
+                .filter(|a| a.state == AlertState::Pending)
+                .map_or(now, |a| a.since);
+            health.alert = Some(Alert {
+                since,
+                ..self.alert(rule, AlertState::Pending, now)
+            });
+            self.active.insert(key, health);
+        }
+        resolved
+    }
+    // 处于firing/flapping的告警 即已通知过、恢复时需要再通知的告警
+    pub fn firing(&self) -> impl Iterator<Item = &HealthInfo> {
+        self.active.values().filter(|h| {
+            h.alert
+                .as_ref()
+                .is_some_and(|a| matches!(a.state, AlertState::Firing | AlertState::Flapping))
+        })
+    }
+    // 处于pending/firing的告警
+    pub fn list(&self) -> Vec<HealthInfo> {
+        self.active.values().cloned().collect()
+    }
+    // 确认firing的告警 之后不再重复通知
+    pub fn ack(&mut self, id: &str, by: &str, now: u64) -> Option<HealthInfo> {
+        let info = self.active.values_mut().find(|h| {
+            h.alert
+                .as_ref()
+                .is_some_and(|a| a.id == id && a.state == AlertState::Firing)
+        })?;
+        let alert = info.alert.as_mut()?;
+        alert.acked_by = Some(by.to_string());
+        alert.acked_at = Some(now);
+        Some(info.clone())
+    }
+    // 清除已过期的确认 恢复重复通知
+    pub fn expire_acks(&mut self, now: u64) {
+        if self.ack_timeout_secs == 0 {
+            return;
+        }
+        for alert in self.active.values_mut().filter_map(|h| h.alert.as_mut()) {
+            if alert
+                .acked_at
+                .is_some_and(|t| now.saturating_sub(t) >= self.ack_timeout_secs)
+            {
+                alert.acked_by = None;
+                alert.acked_at = None;
+            }
+        }
+    }
+    // 最近的状态变化 新的在前
+    pub fn history(&self) -> impl Iterator<Item = &Transition> {
+        self.history.iter().rev()
+    }
+    // 目标下线后不再跟踪其告警
+    pub fn remove(&mut self, target: &Target, now: u64) {
+        self.flaps.remove(&target.key());
+        if let Some(info) = self.active.remove(&target.key()) {
+            let from = info.alert.as_ref().map(|a| a.state);
+            self.record(from, None, info.status, info, now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn health(status: HealthStatus) -> HealthInfo {
+        HealthInfo {
+            target: Target::Service("api".to_string(), None),
+            status,
+            alert: None,
+        }
+    }
+
+    fn state(info: &Option<HealthInfo>) -> Option<AlertState> {
+        info.as_ref()
+            .and_then(|h| h.alert.as_ref())
+            .map(|a| a.state)
+    }
+
+    #[test]
+    fn notifies_on_transitions_only() {
+        let mut book = AlertBook::default();
+        assert!(book
+            .update(health(HealthStatus::Green), "health", true, 0)
+            .is_none());
+        let fired = book.update(health(HealthStatus::Red), "health", false, 10);
+        assert_eq!(state(&fired), Some(AlertState::Firing));
+        assert!(book
+            .update(health(HealthStatus::Red), "health", false, 20)
+            .is_none());
+        // 级别变化
+        let changed = book.update(health(HealthStatus::Yellow), "health", false, 30);
+        assert_eq!(state(&changed), Some(AlertState::Firing));
+        assert!(book
+            .update(health(HealthStatus::Yellow), "health", false, 130)
+            .is_none());
+        assert_eq!(book.firing().count(), 1);
+        let resolved = book.update(health(HealthStatus::Green), "health", false, 140);
+        assert_eq!(state(&resolved), Some(AlertState::Resolved));
+        let resolved = resolved.unwrap();
+        assert_eq!(resolved.dedup_key().unwrap(), "service/api/health");
+        assert_eq!(resolved.alert.unwrap().id, fired.unwrap().alert.unwrap().id);
+        assert!(book
+            .update(health(HealthStatus::Green), "health", false, 150)
+            .is_none());
+        assert_eq!(book.firing().count(), 0);
+    }
+
+    #[test]
+    fn rules_on_one_target_share_an_incident() {
+        let mut book = AlertBook::default();
+        let node = |status| HealthInfo {
+            target: Target::Node("db-1".to_string(), None),
+            status,
+            alert: None,
+        };
+        let disk = book.update(node(HealthStatus::Yellow), "disk_per", false, 0);
+        assert_eq!(
+            disk.as_ref().unwrap().dedup_key().unwrap(),
+            "node/db-1/disk_per"
+        );
+        // 第二条规则越限 仍是同一个告警与事件
+        let both = book.update(
+            node(HealthStatus::Red),
+            "disk_per+mem_status_per",
+            false,
+            10,
+        );
+        let both = both.unwrap();
+        assert_eq!(both.dedup_key().unwrap(), "node/db-1/disk_per");
+        assert_eq!(
+            both.alert.as_ref().unwrap().id,
+            disk.unwrap().alert.unwrap().id
+        );
+        assert_eq!(book.firing().count(), 1);
+        let resolved = book
+            .update(node(HealthStatus::Green), "", false, 20)
+            .unwrap();
+        assert_eq!(resolved.dedup_key().unwrap(), "node/db-1/disk_per");
+        // 恢复后只有内存越限 为新的告警
+        let mem = book.update(node(HealthStatus::Red), "mem_status_per", false, 30);
+        let mem = mem.unwrap().alert.unwrap();
+        assert_ne!(mem.id, both.alert.unwrap().id);
+        assert_eq!(mem.rule, "mem_status_per");
+    }
+
+    #[test]
+    fn history_records_transitions() {
+        let mut book = AlertBook::default();
+        book.update(health(HealthStatus::Green), "health", true, 0);
+        book.update(health(HealthStatus::Green), "health", true, 5);
+        book.update(health(HealthStatus::Red), "health", false, 10);
+        book.update(health(HealthStatus::Red), "health", false, 20);
+        book.update(health(HealthStatus::Yellow), "health", false, 30);
+        book.update(health(HealthStatus::Green), "health", false, 40);
+        book.update(health(HealthStatus::Green), "health", true, 50);
+        book.remove(&health(HealthStatus::Green).target, 60);
+        let history: Vec<_> = book
+            .history()
+            .map(|t| (t.at, t.from, t.to, t.info.status, t.severity))
+            .collect();
+        use AlertState::*;
+        use HealthStatus::*;
+        assert_eq!(
+            history,
+            vec![
+                (60, Some(Pending), None, Green, Green),
+                (50, None, Some(Pending), Green, Green),
+                (40, Some(Firing), Some(Resolved), Green, Yellow),
+                (30, Some(Firing), Some(Firing), Yellow, Yellow),
+                (10, Some(Pending), Some(Firing), Red, Red),
+                (0, None, Some(Pending), Green, Green),
+            ]
+        );
+    }
+
+    #[test]
+    fn flapping_notifies_once_until_stable() {
+        let flapping = Flapping {
+            window_secs: 100,
+            threshold: 4,
+            recover_threshold: 2,
+        };
+        let mut book = AlertBook::new(AckSigner::default(), 0, Some(flapping));
+        let status = |i: u64| {
+            if i.is_multiple_of(2) {
+                HealthStatus::Red
+            } else {
+                HealthStatus::Green
+            }
+        };
+        let mut events = Vec::new();
+        for i in 0..10 {
+            let event = book.update(health(status(i)), "health", false, i * 10);
+            events.push(state(&event));
+        }
+        // 第4次变化时转为flapping 之后不再通知
+        assert_eq!(
+            events,
+            vec![
+                Some(AlertState::Firing),
+                Some(AlertState::Resolved),
+                Some(AlertState::Firing),
+                Some(AlertState::Resolved),
+                Some(AlertState::Flapping),
+                None,
+                None,
+                None,
+                None,
+                None,
+            ]
+        );
+        assert_eq!(book.list().len(), 1);
+        assert_eq!(book.firing().count(), 1);
+        // 保持Red 窗口内的变化逐渐移出 稳定后按当前状态通知
+        let mut stable = None;
+        for t in (100..300).step_by(10) {
+            if let Some(event) = book.update(health(HealthStatus::Red), "health", false, t) {
+                stable = Some((t, event));
+                break;
+            }
+        }
+        let (t, event) = stable.unwrap();
+        assert_eq!(t, 190);
+        assert_eq!(event.alert.unwrap().state, AlertState::Firing);
+        let resolved = book.update(health(HealthStatus::Green), "health", false, 300);
+        assert_eq!(state(&resolved), Some(AlertState::Resolved));
+
+        // 在恢复的采样上开始抖动 仍按窗口内的Red通知
+        let mut flapped = None;
+        for i in 1..10 {
+            let event = book.update(health(status(i)), "health", false, 1000 + i * 10);
+            if state(&event) == Some(AlertState::Flapping) {
+                flapped = Some((i, event.unwrap()));
+                break;
+            }
+        }
+        let (i, event) = flapped.unwrap();
+        assert_eq!(status(i), HealthStatus::Green);
+        assert_eq!(event.status, HealthStatus::Red);
+    }
+
+    #[test]
+    fn ack_lasts_until_timeout() {
+        let signer = AckSigner::new(Some("http://monitor:3000/".to_string()), None);
+        let mut book = AlertBook::new(signer.clone(), 60, None);
+        let fired = book
+            .update(health(HealthStatus::Red), "health", false, 0)
+            .unwrap();
+        let alert = fired.alert.unwrap();
+        let url = alert.ack_url.unwrap();
+        let sig = url.split("sig=").nth(1).unwrap();
+        assert!(url.starts_with(&format!("http://monitor:3000/alerts/{}/ack?", alert.id)));
+        assert!(signer.verify(&alert.id, sig));
+        assert!(!signer.verify("other", sig));
+
+        assert!(book.ack("unknown", "bob", 10).is_none());
+        let acked = book.ack(&alert.id, "bob", 10).unwrap();
+        assert_eq!(acked.alert.unwrap().acked_by.as_deref(), Some("bob"));
+        // 状态未变时保留确认
+        book.update(health(HealthStatus::Red), "health", false, 20);
+        book.expire_acks(69);
+        assert!(book
+            .firing()
+            .all(|h| h.alert.as_ref().unwrap().acked_at.is_some()));
+        book.expire_acks(70);
+        assert!(book
+            .firing()
+            .all(|h| h.alert.as_ref().unwrap().acked_at.is_none()));
+    }
+}
@@ -1,13 +1,20 @@
+use crate::config::model::{AlertMatcher, Rule, Severity, TargetKind};
+use crate::core::ack::AckSigner;
+use crate::core::alert::Alerts;
+use crate::core::dispatch::Matcher;
 use crate::core::doctor::*;
 use crate::core::ent::*;
+use crate::core::silence::{NewSilence, Silences};
 
 use axum::Json;
 // The query parameters for nodes index
 use axum::{
     extract::{Path, Query, State},
     http::StatusCode,
-    response::IntoResponse,
+    response::{Html, IntoResponse},
+    Form,
 };
+use handlebars::html_escape;
 use serde::Deserialize;
 
 use std::{
@@ -59,6 +66,8 @@ pub struct UpsertNode {
     disk_f_60: String,
     disk_per_60: String,
     disk_status: String,
+    #[serde(default)]
+    labels: HashMap<String, String>,
 }
 
 pub async fn node_upsert(
@@ -88,6 +97,7 @@ pub async fn node_upsert(
             .unwrap()
             .as_secs(),
         status_msg: Option::None,
+        labels: input.labels,
     };
     state
         .db
@@ -101,12 +111,27 @@ pub async fn node_upsert(
     tx.send(Event::Heartbeat(HealthInfo {
         target: Target::Node(String::from(&todo.id), Some(todo.clone())),
         status: health,
+        alert: None,
     }))
     .await
     .unwrap();
     (StatusCode::OK, Json(todo))
 }
 
+pub async fn node_show(
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    state
+        .db
+        .read()
+        .unwrap()
+        .get(&id)
+        .cloned()
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
 pub async fn node_delete(
     Path(id): Path<String>,
     State(state): State<Arc<AppState>>,
@@ -125,8 +150,437 @@ pub async fn node_delete(
     }
 }
 
+// 查看节点当前生效的阈值规则
+pub async fn node_thresholds(
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    match state.db.read().unwrap().get(&id) {
+        Some(node) => Ok(Json(state.dc.rules_for(node))),
+        None => state
+            .dc
+            .thresholds(&id)
+            .map(Json)
+            .ok_or(StatusCode::NOT_FOUND),
+    }
+}
+
+// 为节点设置阈值 同一指标会覆盖配置文件中的规则
+pub async fn node_thresholds_update(
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Json(rules): Json<Vec<Rule>>,
+) -> impl IntoResponse {
+    state.dc.set_thresholds(id, rules.clone());
+    (StatusCode::OK, Json(rules))
+}
+
+pub async fn node_thresholds_delete(
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    if state.dc.remove_thresholds(&id).is_some() {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+// 各服务最近一次的检查结果
+pub async fn services_index(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let services = state
+        .services
+        .read()
+        .unwrap()
+        .values()
+        .cloned()
+        .collect::<Vec<_>>();
+    Json(services)
+}
+
+pub async fn service_show(
+    Path(name): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    state
+        .services
+        .read()
+        .unwrap()
+        .get(&name)
+        .cloned()
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+pub async fn silences_index(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(state.silences.read().unwrap().list())
+}
+
+pub async fn silence_create(
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<NewSilence>,
+) -> impl IntoResponse {
+    let added = state.silences.write().unwrap().add(input, timestamp());
+    match added {
+        Ok((silence, snapshot)) => match snapshot.save().await {
+            Ok(()) => Ok((StatusCode::CREATED, Json(silence))),
+            Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+        },
+        Err(e) => Err((StatusCode::BAD_REQUEST, e)),
+    }
+}
+
+pub async fn silence_delete(
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let removed = state.silences.write().unwrap().remove(&id);
+    match removed {
+        Some(snapshot) => match snapshot.save().await {
+            Ok(()) => Ok(StatusCode::NO_CONTENT),
+            Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+        },
+        None => Ok(StatusCode::NOT_FOUND),
+    }
+}
+
+// 告警查询条件 target为节点id或服务名的glob
+#[derive(Debug, Deserialize, Default)]
+pub struct AlertFilter {
+    pub severity: Option<Severity>,
+    pub kind: Option<TargetKind>,
+    pub target: Option<String>,
+    // 仅用于历史记录
+    pub since: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+impl AlertFilter {
+    fn matcher(&self) -> Result<Matcher, (StatusCode, String)> {
+        Matcher::new(AlertMatcher {
+            severity: self.severity,
+            kind: self.kind,
+            name: self.target.clone(),
+            labels: HashMap::new(),
+        })
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+    }
+}
+
+// 处于pending/firing的告警 含确认状态
+pub async fn alerts_index(
+    filter: Option<Query<AlertFilter>>,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let Query(filter) = filter.unwrap_or_default();
+    let matcher = filter.matcher()?;
+    let mut alerts = state.alerts.read().unwrap().list();
+    alerts.retain(|h| matcher.matches(h));
+    alerts.sort_by_key(|h| h.alert.as_ref().map(|a| a.since));
+    Ok::<_, (StatusCode, String)>(Json(alerts))
+}
+
+// 告警状态变化的历史 新的在前 severity按告警的级别过滤 恢复记录同样保留
+pub async fn alerts_history(
+    filter: Option<Query<AlertFilter>>,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let Query(filter) = filter.unwrap_or_default();
+    let matcher = filter.matcher()?;
+    let history = state
+        .alerts
+        .read()
+        .unwrap()
+        .history()
+        .filter(|t| t.at >= filter.since.unwrap_or(0) && matcher.matches_as(&t.info, t.severity))
+        .take(filter.limit.unwrap_or(usize::MAX))
+        .cloned()
+        .collect::<Vec<_>>();
+    Ok::<_, (StatusCode, String)>(Json(history))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AckInput {
+    pub by: String,
+}
+
+pub async fn alert_ack(
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<AckInput>,
+) -> impl IntoResponse {
+    ack(&state, &id, &input.by).map(Json)
+}
+
+// 通知中的一键确认链接 需校验签名
+// 邮件网关与聊天工具会预先抓取消息中的链接 GET只返回确认页 填写确认人提交后才确认
+#[derive(Debug, Deserialize)]
+pub struct AckLink {
+    pub sig: String,
+}
+
+pub async fn alert_ack_link(
+    Path(id): Path<String>,
+    Query(link): Query<AckLink>,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    if !state.signer.verify(&id, &link.sig) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let alerts = state.alerts.read().unwrap().list();
+    let info = alerts
+        .iter()
+        .find(|h| h.alert.as_ref().is_some_and(|a| a.id == id))
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Html(format!(
+        r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>确认告警</title></head>
+<body>
+<p>{} {}</p>
+<pre>{}</pre>
+<form method="post" action="ack/confirm">
+<input type="hidden" name="sig" value="{}">
+<label>确认人 <input name="by" required></label>
+<button type="submit">确认</button>
+</form>
+</body>
+</html>
+"#,
+        html_escape(&info.target.key()),
+        html_escape(&format!("{:?}", info.status)),
+        html_escape(info.msg()),
+        html_escape(&link.sig),
+    )))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AckConfirm {
+    pub sig: String,
+    pub by: String,
+}
+
+// 确认页提交的表单
+pub async fn alert_ack_confirm(
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Form(form): Form<AckConfirm>,
+) -> impl IntoResponse {
+    if !state.signer.verify(&id, &form.sig) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let info = ack(&state, &id, &form.by)?;
+    let by = info.alert.and_then(|a| a.acked_by).unwrap_or_default();
+    Ok(Html(format!(
+        "<!DOCTYPE html>\n<p>{} 已由 {} 确认</p>\n",
+        html_escape(&info.target.key()),
+        html_escape(&by)
+    )))
+}
+
+// 只有firing的告警可以确认 已恢复或不存在时返回404 确认人不能为空
+fn ack(state: &AppState, id: &str, by: &str) -> Result<HealthInfo, StatusCode> {
+    let by = by.trim();
+    if by.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let info = state.alerts.write().unwrap().ack(id, by, timestamp());
+    match info {
+        Some(info) => {
+            tracing::info!("alert {} acked by {}", id, by);
+            Ok(info)
+        }
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
 pub struct AppState {
     pub db: RwLock<HashMap<String, Node>>,
+    pub services: Arc<RwLock<HashMap<String, Service>>>,
+    pub silences: Silences,
+    pub alerts: Alerts,
+    pub signer: AckSigner,
     pub tx: mpsc::Sender<Event>,
     pub dc: Doctor,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::alert::AlertBook;
+    use crate::core::silence::SilenceStore;
+    use axum::{routing::get, Router};
+    use uuid::Uuid;
+
+    fn state() -> Arc<AppState> {
+        let signer = AckSigner::new(
+            Some("http://monitor".to_string()),
+            Some("s3cret".to_string()),
+        );
+        let path = std::env::temp_dir().join(format!("silences-{}.json", Uuid::new_v4()));
+        Arc::new(AppState {
+            db: RwLock::new(HashMap::new()),
+            services: Arc::default(),
+            silences: Arc::new(RwLock::new(SilenceStore::open(path).unwrap())),
+            alerts: Arc::new(RwLock::new(AlertBook::new(signer.clone(), 0, None))),
+            signer,
+            tx: mpsc::channel(1).0,
+            dc: Doctor::new(vec![], vec![]).unwrap(),
+        })
+    }
+
+    // 使目标进入firing 返回告警
+    fn fire(state: &AppState, target: Target, status: HealthStatus, at: u64) -> Alert {
+        let health = HealthInfo {
+            target,
+            status,
+            alert: None,
+        };
+        let mut alerts = state.alerts.write().unwrap();
+        alerts
+            .update(health, "health", false, at)
+            .unwrap()
+            .alert
+            .unwrap()
+    }
+
+    async fn serve(state: Arc<AppState>) -> String {
+        let app = Router::new()
+            .route("/alerts", get(alerts_index))
+            .route("/alerts/history", get(alerts_history))
+            .route("/alerts/:id/ack", get(alert_ack_link).post(alert_ack))
+            .route(
+                "/alerts/:id/ack/confirm",
+                axum::routing::post(alert_ack_confirm),
+            )
+            .with_state(state);
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn ack_link_needs_confirmation() {
+        let state = state();
+        let alert = fire(
+            &state,
+            Target::Service("api".to_string(), None),
+            HealthStatus::Red,
+            0,
+        );
+        let base = serve(state.clone()).await;
+        let link = alert.ack_url.unwrap().replace("http://monitor", &base);
+        let sig = link.rsplit('=').next().unwrap().to_string();
+        let client = reqwest::Client::new();
+
+        // 预先抓取链接不会确认告警
+        let page = client.get(&link).send().await.unwrap();
+        assert_eq!(page.status(), StatusCode::OK);
+        let page = page.text().await.unwrap();
+        assert!(page.contains(r#"<form method="post" action="ack/confirm">"#));
+        assert!(state.alerts.read().unwrap().list()[0]
+            .alert
+            .as_ref()
+            .unwrap()
+            .acked_by
+            .is_none());
+        let forged = client
+            .get(format!("{}/alerts/{}/ack?sig=00", base, alert.id))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(forged.status(), StatusCode::FORBIDDEN);
+
+        let confirm = format!("{}/alerts/{}/ack/confirm", base, alert.id);
+        let anonymous = client
+            .post(&confirm)
+            .form(&[("sig", sig.as_str()), ("by", " ")])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(anonymous.status(), StatusCode::BAD_REQUEST);
+        let acked = client
+            .post(&confirm)
+            .form(&[("sig", sig.as_str()), ("by", "alice")])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(acked.status(), StatusCode::OK);
+        let alerts: Vec<serde_json::Value> = client
+            .get(format!("{}/alerts", base))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(alerts[0]["alert"]["acked_by"], "alice");
+    }
+
+    async fn get_json(url: String) -> (StatusCode, serde_json::Value) {
+        let resp = reqwest::get(url).await.unwrap();
+        let status = resp.status();
+        (status, resp.json().await.unwrap_or_default())
+    }
+
+    fn names(v: &serde_json::Value, pointer: &str) -> Vec<String> {
+        v.as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t.pointer(pointer).unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn alerts_are_filtered() {
+        let state = state();
+        let service = |name: &str| Target::Service(name.to_string(), None);
+        fire(&state, service("api"), HealthStatus::Red, 10);
+        fire(&state, service("web"), HealthStatus::Yellow, 20);
+        fire(
+            &state,
+            Target::Node("db-1".to_string(), None),
+            HealthStatus::Red,
+            30,
+        );
+        // api恢复
+        state.alerts.write().unwrap().update(
+            HealthInfo {
+                target: service("api"),
+                status: HealthStatus::Green,
+                alert: None,
+            },
+            "health",
+            false,
+            40,
+        );
+        let base = serve(state).await;
+
+        let (_, alerts) = get_json(format!("{}/alerts?severity=Red", base)).await;
+        assert_eq!(names(&alerts, "/target/Node/0"), vec![r#""db-1""#]);
+        let (_, alerts) = get_json(format!("{}/alerts?kind=service", base)).await;
+        assert_eq!(names(&alerts, "/target/Service/0"), vec![r#""web""#]);
+        let (_, alerts) = get_json(format!("{}/alerts?target=db-*", base)).await;
+        assert_eq!(alerts.as_array().unwrap().len(), 1);
+        let (status, _) = get_json(format!("{}/alerts?target=db-[", base)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // 红色告警的恢复记录按恢复前的级别匹配
+        let (_, history) = get_json(format!("{}/alerts/history?severity=Red", base)).await;
+        assert_eq!(
+            names(&history, "/to"),
+            vec![r#""Resolved""#, r#""Firing""#, r#""Firing""#]
+        );
+        assert_eq!(names(&history, "/at"), vec!["40", "30", "10"]);
+        let (_, history) = get_json(format!(
+            "{}/alerts/history?kind=service&since=20&limit=1",
+            base
+        ))
+        .await;
+        assert_eq!(names(&history, "/at"), vec!["40"]);
+        let (_, history) = get_json(format!("{}/alerts/history?since=41", base)).await;
+        assert!(history.as_array().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,231 @@
+use crate::config::model::{Assertion, Comparison, Expect, Severity};
+use crate::core::ent::HealthStatus;
+use regex::Regex;
+use serde_json::Value;
+use serde_json_path::JsonPath;
+use std::cmp;
+
+// 预先编译好的响应断言
+enum Compiled {
+    Contains(String),
+    Regex(Regex),
+    JsonPath {
+        raw: String,
+        path: JsonPath,
+        equals: Option<Value>,
+        compare: Option<(Comparison, f64)>,
+    },
+    Size {
+        min: usize,
+        max: usize,
+    },
+}
+
+pub struct Assertions {
+    assertions: Vec<(Compiled, Severity)>,
+}
+
+impl Compiled {
+    fn new(expect: Expect) -> Result<Compiled, String> {
+        Ok(match expect {
+            Expect::Contains { value } => Compiled::Contains(value),
+            Expect::Regex { pattern } => Compiled::Regex(
+                Regex::new(&pattern).map_err(|e| format!("bad regex {}: {}", pattern, e))?,
+            ),
+            Expect::JsonPath {
+                path,
+                equals,
+                op,
+                value,
+            } => {
+                let compare = match (op, value) {
+                    (op, Some(value)) => Some((op.unwrap_or_default(), value)),
+                    (Some(_), None) => return Err(format!("json_path {}: op needs value", path)),
+                    (None, None) => None,
+                };
+                if equals.is_none() && compare.is_none() {
+                    return Err(format!("json_path {}: equals or value is required", path));
+                }
+                Compiled::JsonPath {
+                    path: JsonPath::parse(&path)
+                        .map_err(|e| format!("bad json_path {}: {}", path, e))?,
+                    raw: path,
+                    equals,
+                    compare,
+                }
+            }
+            Expect::Size { min, max } => Compiled::Size {
+                min: min.unwrap_or(0),
+                max: max.unwrap_or(usize::MAX),
+            },
+        })
+    }
+    // 未通过时返回原因
+    fn check(&self, body: &[u8], json: Option<&Value>) -> Option<String> {
+        match self {
+            Compiled::Contains(value) => (!String::from_utf8_lossy(body).contains(value.as_str()))
+                .then(|| format!("body does not contain {:?}", value)),
+            Compiled::Regex(re) => (!re.is_match(&String::from_utf8_lossy(body)))
+                .then(|| format!("body does not match /{}/", re)),
+            Compiled::JsonPath {
+                raw,
+                path,
+                equals,
+                compare,
+            } => {
+                let Some(json) = json else {
+                    return Some(format!("{}: body is not json", raw));
+                };
+                let Some(found) = path.query(json).first() else {
+                    return Some(format!("{}: not found", raw));
+                };
+                if let Some(equals) = equals.as_ref().filter(|e| !loose_eq(found, e)) {
+                    return Some(format!("{} is {}, expected {}", raw, found, equals));
+                }
+                let (op, value) = (*compare)?;
+                match found.as_f64() {
+                    Some(n) if op.hit(n, value) => None,
+                    Some(n) => Some(format!("{} is {}, expected {:?} {}", raw, n, op, value)),
+                    None => Some(format!("{} is {}, not a number", raw, found)),
+                }
+            }
+            // 响应体可能已按上限截断 超过max时只报告max
+            Compiled::Size { max, .. } if body.len() > *max => {
+                Some(format!("body size over {} bytes", max))
+            }
+            Compiled::Size { min, .. } => (body.len() < *min)
+                .then(|| format!("body size {} bytes under {} bytes", body.len(), min)),
+        }
+    }
+}
+
+// 配置中的 "200" 与响应中的 200 视为相等
+fn loose_eq(found: &Value, expected: &Value) -> bool {
+    match (found, expected) {
+        (Value::Number(_) | Value::Bool(_), Value::String(s)) => {
+            s.parse::<Value>().is_ok_and(|v| v == *found)
+        }
+        _ => found == expected,
+    }
+}
+
+impl Assertions {
+    pub fn new(assertions: Vec<Assertion>) -> Result<Assertions, String> {
+        let assertions = assertions
+            .into_iter()
+            .map(|a| Compiled::new(a.expect).map(|c| (c, a.severity)))
+            .collect::<Result<_, _>>()?;
+        Ok(Assertions { assertions })
+    }
+    // 读取响应体的长度上限 没有断言时不必读取
+    // 只有size断言时读到最大的边界多1字节 每条断言即可判断 其余断言需要完整的响应体
+    pub fn body_limit(&self) -> Option<usize> {
+        let mut limit = None;
+        for (assertion, _) in &self.assertions {
+            let bound = match assertion {
+                Compiled::Size { min, max } if *max != usize::MAX => cmp::max(*min, *max),
+                _ => return Some(usize::MAX),
+            };
+            limit = cmp::max(limit, Some(bound.saturating_add(1)));
+        }
+        limit
+    }
+    // 返回最严重的级别及每条未通过断言的说明
+    pub fn check(&self, body: &[u8]) -> (HealthStatus, Vec<String>) {
+        let json: Option<Value> = serde_json::from_slice(body).ok();
+        let mut status = HealthStatus::Green;
+        let mut msgs = Vec::new();
+        for (assertion, severity) in &self.assertions {
+            let Some(reason) = assertion.check(body, json.as_ref()) else {
+                continue;
+            };
+            match severity {
+                Severity::Red => {
+                    status = HealthStatus::Red;
+                    msgs.push(format!("Error: assertion failed: {}", reason));
+                }
+                Severity::Yellow => {
+                    if status == HealthStatus::Green {
+                        status = HealthStatus::Yellow;
+                    }
+                    msgs.push(format!("Warn: assertion failed: {}", reason));
+                }
+            }
+        }
+        (status, msgs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assertions(yaml: &str) -> Assertions {
+        Assertions::new(serde_yaml::from_str(yaml).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn body_assertions_with_severity() {
+        let assertions = assertions(
+            r#"
+- {kind: contains, value: '"status"'}
+- {kind: regex, pattern: 'version":\s*"\d+\.', severity: yellow}
+- {kind: json_path, path: $.status, equals: UP}
+- {kind: json_path, path: $.queue.depth, op: gt, value: 100, severity: yellow}
+- {kind: size, max: 200, severity: yellow}
+"#,
+        );
+        let up = br#"{"status":"UP","version":"1.2","queue":{"depth":150}}"#;
+        assert_eq!(assertions.check(up), (HealthStatus::Green, vec![]));
+
+        let slow = br#"{"status":"UP","version":"dev","queue":{"depth":3}}"#;
+        let (status, msgs) = assertions.check(slow);
+        assert_eq!(status, HealthStatus::Yellow);
+        assert_eq!(msgs.len(), 2);
+        assert!(msgs[1].contains("$.queue.depth is 3, expected Gt 100"));
+
+        let down = br#"{"status":"DOWN"}"#;
+        let (status, msgs) = assertions.check(down);
+        assert_eq!(status, HealthStatus::Red);
+        assert!(msgs
+            .iter()
+            .any(|m| m == r#"Error: assertion failed: $.status is "DOWN", expected "UP""#));
+        assert!(msgs.iter().any(|m| m.contains("$.queue.depth: not found")));
+
+        let (status, msgs) = assertions.check(&[b' '; 300]);
+        assert_eq!(status, HealthStatus::Red);
+        assert!(msgs.iter().any(|m| m.contains("body is not json")));
+        assert!(msgs.iter().any(|m| m.contains("body size over 200 bytes")));
+    }
+
+    #[test]
+    fn body_limit_covers_every_size_assertion() {
+        assert_eq!(assertions("[]").body_limit(), None);
+        let sizes = assertions(
+            r#"
+- {kind: size, max: 100, severity: yellow}
+- {kind: size, max: 200}
+"#,
+        );
+        assert_eq!(sizes.body_limit(), Some(201));
+        let (status, msgs) = sizes.check(&[b' '; 201]);
+        assert_eq!(status, HealthStatus::Red);
+        assert_eq!(msgs.len(), 2);
+        let mixed = assertions("[{kind: size, max: 100}, {kind: contains, value: ok}]");
+        assert_eq!(mixed.body_limit(), Some(usize::MAX));
+    }
+
+    #[test]
+    fn invalid_assertions_fail_at_startup() {
+        let bad = |yaml: &str| Assertions::new(serde_yaml::from_str(yaml).unwrap()).err();
+        assert!(bad("[{kind: regex, pattern: '('}]")
+            .unwrap()
+            .contains("bad regex"));
+        assert!(bad("[{kind: json_path, path: 'status'}]")
+            .unwrap()
+            .contains("equals or value"));
+        assert!(bad("[{kind: json_path, path: 'status', equals: 1}]")
+            .unwrap()
+            .contains("bad json_path"));
+    }
+}
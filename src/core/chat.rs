@@ -0,0 +1,237 @@
+use crate::config::model::ChatHook;
+use crate::core::ent::HealthInfo;
+use crate::core::notifier::Notifier;
+use crate::core::render::{color, link, title, worst, EventView};
+use crate::core::webhook::{accept_any, deliver, http_client};
+use reqwest::{Client, Url};
+use serde_json::{json, Value};
+
+// Discord单条消息最多10个embed
+const DISCORD_MAX_EMBEDS: usize = 10;
+
+#[derive(Debug, Clone, Copy)]
+pub enum ChatKind {
+    Slack,
+    Teams,
+    Discord,
+}
+
+// 海外常用聊天工具的incoming webhook 按状态着色 附带目标在本服务API中的链接
+pub struct ChatNotifier {
+    name: String,
+    kind: ChatKind,
+    url: Url,
+    public_url: Option<String>,
+    retries: u32,
+    client: Client,
+}
+
+impl ChatNotifier {
+    pub fn new(
+        name: String,
+        kind: ChatKind,
+        config: ChatHook,
+        public_url: Option<String>,
+    ) -> Result<ChatNotifier, String> {
+        let url =
+            Url::parse(&config.url).map_err(|e| format!("{:?} {}: bad url: {}", kind, name, e))?;
+        Ok(ChatNotifier {
+            name,
+            kind,
+            url,
+            public_url,
+            retries: config.retries,
+            client: http_client(config.timeout_secs),
+        })
+    }
+    fn link(&self, info: &HealthInfo) -> Option<String> {
+        self.public_url.as_deref().map(|base| link(base, info))
+    }
+    fn message(&self, events: &[HealthInfo]) -> Value {
+        let title = title(events);
+        match self.kind {
+            ChatKind::Slack => {
+                let attachments: Vec<Value> = events
+                    .iter()
+                    .map(|info| {
+                        let view = EventView::new(info);
+                        let mut text = format!("*{} {} {}*\n", view.label(), view.kind, view.name);
+                        for f in &view.metrics {
+                            text.push_str(&format!("• {}: {}\n", f.name, f.value));
+                        }
+                        if !view.msg.is_empty() {
+                            text.push_str(&format!("> {}\n", view.msg.replace('\n', "\n> ")));
+                        }
+                        if let Some(url) = self.link(info) {
+                            text.push_str(&format!("<{}|View {}>", url, view.name));
+                        }
+                        if let Some(url) = &view.ack_url {
+                            text.push_str(&format!(" <{}|Acknowledge>", url));
+                        }
+                        json!({
+                            "color": view.color,
+                            "blocks": [{"type": "section", "text": {"type": "mrkdwn", "text": text}}],
+                        })
+                    })
+                    .collect();
+                json!({"text": title, "attachments": attachments})
+            }
+            ChatKind::Teams => {
+                let sections: Vec<Value> = events
+                    .iter()
+                    .map(|info| {
+                        let view = EventView::new(info);
+                        let facts: Vec<Value> = view
+                            .metrics
+                            .iter()
+                            .map(|f| json!({"name": f.name, "value": f.value}))
+                            .collect();
+                        let mut section = json!({
+                            "activityTitle": format!("{} {} {}", view.label(), view.kind, view.name),
+                            "facts": facts,
+                            "text": view.msg,
+                        });
+                        let mut actions = Vec::new();
+                        if let Some(url) = self.link(info) {
+                            actions.push(json!({
+                                "@type": "OpenUri",
+                                "name": format!("View {}", view.name),
+                                "targets": [{"os": "default", "uri": url}],
+                            }));
+                        }
+                        if let Some(url) = &view.ack_url {
+                            actions.push(json!({
+                                "@type": "OpenUri",
+                                "name": "Acknowledge",
+                                "targets": [{"os": "default", "uri": url}],
+                            }));
+                        }
+                        if !actions.is_empty() {
+                            section["potentialAction"] = json!(actions);
+                        }
+                        section
+                    })
+                    .collect();
+                json!({
+                    "@type": "MessageCard",
+                    "@context": "https://schema.org/extensions",
+                    "summary": title,
+                    "title": title,
+                    "themeColor": color(worst(events)).trim_start_matches('#'),
+                    "sections": sections,
+                })
+            }
+            ChatKind::Discord => {
+                let embeds: Vec<Value> = events
+                    .iter()
+                    .take(DISCORD_MAX_EMBEDS)
+                    .map(|info| {
+                        let view = EventView::new(info);
+                        let fields: Vec<Value> = view
+                            .metrics
+                            .iter()
+                            .map(|f| json!({"name": f.name, "value": f.value, "inline": true}))
+                            .collect();
+                        let rgb = u32::from_str_radix(view.color.trim_start_matches('#'), 16);
+                        let mut description = view.msg.clone();
+                        if let Some(url) = &view.ack_url {
+                            description.push_str(&format!("\n[Acknowledge]({})", url));
+                        }
+                        let mut embed = json!({
+                            "title": format!("{} {} {}", view.label(), view.kind, view.name),
+                            "description": description,
+                            "color": rgb.unwrap_or(0),
+                            "fields": fields,
+                        });
+                        if let Some(url) = self.link(info) {
+                            embed["url"] = json!(url);
+                        }
+                        embed
+                    })
+                    .collect();
+                let mut content = title;
+                if events.len() > DISCORD_MAX_EMBEDS {
+                    content.push_str(&format!(
+                        " ({} more not shown)",
+                        events.len() - DISCORD_MAX_EMBEDS
+                    ));
+                }
+                json!({"content": content, "embeds": embeds})
+            }
+        }
+    }
+}
+
+impl Notifier for ChatNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn notify(&self, events: Vec<HealthInfo>) {
+        let body = self.message(&events);
+        match self.client.post(self.url.clone()).json(&body).build() {
+            Ok(request) => {
+                tokio::spawn(deliver(
+                    self.client.clone(),
+                    format!("{:?} {}", self.kind, self.name),
+                    request,
+                    self.retries,
+                    accept_any,
+                ));
+            }
+            Err(e) => tracing::error!("{:?} {}: build request fail: {}", self.kind, self.name, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ent::*;
+
+    fn chat(kind: ChatKind) -> ChatNotifier {
+        let config = ChatHook {
+            url: "https://hooks.example.com/x".to_string(),
+            timeout_secs: 5,
+            retries: 0,
+        };
+        ChatNotifier::new(
+            "team".to_string(),
+            kind,
+            config,
+            Some("http://monitor:3000/".to_string()),
+        )
+        .unwrap()
+    }
+
+    fn events() -> Vec<HealthInfo> {
+        vec![HealthInfo {
+            target: Target::Node("db-1".to_string(), None),
+            status: HealthStatus::Yellow,
+            alert: None,
+        }]
+    }
+
+    #[test]
+    fn messages_are_colored_and_linked() {
+        let slack = chat(ChatKind::Slack).message(&events());
+        assert_eq!(slack["attachments"][0]["color"], "#f0ad4e");
+        let text = slack["attachments"][0]["blocks"][0]["text"]["text"]
+            .as_str()
+            .unwrap();
+        assert!(text.contains("<http://monitor:3000/nodes/db-1|View db-1>"));
+
+        let teams = chat(ChatKind::Teams).message(&events());
+        assert_eq!(teams["themeColor"], "f0ad4e");
+        assert_eq!(
+            teams["sections"][0]["potentialAction"][0]["targets"][0]["uri"],
+            "http://monitor:3000/nodes/db-1"
+        );
+
+        let discord = chat(ChatKind::Discord).message(&events());
+        assert_eq!(discord["embeds"][0]["color"], 0xf0ad4e);
+        assert_eq!(
+            discord["embeds"][0]["url"],
+            "http://monitor:3000/nodes/db-1"
+        );
+    }
+}
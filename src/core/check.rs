@@ -0,0 +1,381 @@
+use crate::config::model::{self, Auth};
+use crate::core::assertion::Assertions;
+use regex::Regex;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    redirect, Certificate, Client, Method, RequestBuilder, StatusCode, Url,
+};
+use std::{fs, ops::RangeInclusive, time::Duration};
+use tokio_native_tls::{native_tls, TlsConnector};
+
+// 启动时编译好的HTTP服务检查 每个服务使用独立的client以应用各自的超时、重定向与证书配置
+pub struct HttpCheck {
+    pub kind: model::CheckKind,
+    pub url: Url,
+    method: Method,
+    headers: HeaderMap,
+    body: Option<String>,
+    auth: Option<Auth>,
+    expect_status: Vec<RangeInclusive<u16>>,
+    pub timeout: Duration,
+    client: Client,
+    // 开启probe_phases时测量TLS握手耗时用 与client的证书配置一致
+    pub tls: Option<TlsConnector>,
+    pub latency_warn_ms: Option<u128>,
+    pub latency_error_ms: Option<u128>,
+    pub assertions: Assertions,
+}
+
+// 端口连通性检查 连接后可发送payload并在超时前读取返回内容校验banner
+pub struct TcpCheck {
+    pub host: String,
+    pub port: u16,
+    pub timeout: Duration,
+    pub payload: Option<Vec<u8>>,
+    pub banner: Option<String>,
+    pub banner_regex: Option<Regex>,
+    pub latency_warn_ms: Option<u128>,
+    pub latency_error_ms: Option<u128>,
+}
+
+pub enum ServiceCheck {
+    // HttpCheck较大 装箱避免枚举按最大成员占用空间
+    Http(Box<HttpCheck>),
+    Tcp(TcpCheck),
+}
+
+impl ServiceCheck {
+    pub fn new(srv: &model::Service) -> Result<ServiceCheck, String> {
+        match srv.kind {
+            model::CheckKind::Tcp => TcpCheck::new(srv).map(ServiceCheck::Tcp),
+            _ => HttpCheck::new(srv).map(|c| ServiceCheck::Http(Box::new(c))),
+        }
+    }
+}
+
+// host:port 可带tcp://前缀 IPv6地址写作[::1]:port
+fn parse_address(s: &str) -> Result<(String, u16), String> {
+    let bad = || format!("bad address {}, expect host:port", s);
+    let addr = s.strip_prefix("tcp://").unwrap_or(s);
+    let (host, port) = addr.rsplit_once(':').ok_or_else(bad)?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = port.parse::<u16>().map_err(|_| bad())?;
+    if host.is_empty() || port == 0 {
+        return Err(bad());
+    }
+    Ok((host.to_string(), port))
+}
+
+// http与tcp检查共有的配置项
+fn validate_common(srv: &model::Service) -> Result<(), String> {
+    if srv.timeout_secs == 0 {
+        return Err("timeout_secs must be positive".to_string());
+    }
+    if let (Some(warn), Some(error)) = (srv.latency_warn_ms, srv.latency_error_ms) {
+        if warn > error {
+            return Err("latency_warn_ms must not exceed latency_error_ms".to_string());
+        }
+    }
+    Ok(())
+}
+
+// 配置了非默认值的HTTP选项 tcp检查不支持
+fn http_options(srv: &model::Service) -> Vec<&'static str> {
+    [
+        (
+            "method",
+            !srv.method.eq_ignore_ascii_case(&model::default_method()),
+        ),
+        ("headers", !srv.headers.is_empty()),
+        ("body", srv.body.is_some()),
+        ("auth", srv.auth.is_some()),
+        (
+            "expect_status",
+            srv.expect_status != model::default_expect_status(),
+        ),
+        (
+            "max_redirects",
+            srv.max_redirects != model::default_max_redirects(),
+        ),
+        ("tls_verify", !srv.tls_verify),
+        ("ca_cert", srv.ca_cert.is_some()),
+        ("probe_phases", srv.probe_phases),
+        ("assertions", !srv.assertions.is_empty()),
+    ]
+    .into_iter()
+    .filter_map(|(name, set)| set.then_some(name))
+    .collect()
+}
+
+impl TcpCheck {
+    pub fn new(srv: &model::Service) -> Result<TcpCheck, String> {
+        let (host, port) = parse_address(&srv.api)?;
+        let unsupported = http_options(srv);
+        if !unsupported.is_empty() {
+            return Err(format!(
+                "{} not supported by tcp checks",
+                unsupported.join(", ")
+            ));
+        }
+        validate_common(srv)?;
+        let banner_regex = srv
+            .banner_regex
+            .as_ref()
+            .map(|p| Regex::new(p).map_err(|e| format!("bad banner_regex {}: {}", p, e)))
+            .transpose()?;
+        Ok(TcpCheck {
+            host,
+            port,
+            timeout: Duration::from_secs(srv.timeout_secs),
+            payload: srv.payload.as_ref().map(|p| p.as_bytes().to_vec()),
+            banner: srv.banner.clone(),
+            banner_regex,
+            latency_warn_ms: srv.latency_warn_ms,
+            latency_error_ms: srv.latency_error_ms,
+        })
+    }
+    pub fn address(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+    // 未配置banner时只检查连通性
+    pub fn expects_banner(&self) -> bool {
+        self.banner.is_some() || self.banner_regex.is_some()
+    }
+    pub fn matches(&self, banner: &str) -> bool {
+        self.banner
+            .as_ref()
+            .is_none_or(|b| banner.contains(b.as_str()))
+            && self
+                .banner_regex
+                .as_ref()
+                .is_none_or(|r| r.is_match(banner))
+    }
+}
+
+// 204、200-299 或 2xx
+fn parse_status(s: &str) -> Result<RangeInclusive<u16>, String> {
+    let bad = || format!("bad status {}", s);
+    let code = |c: &str| c.trim().parse::<u16>().map_err(|_| bad());
+    let range = if let Some(class) = s.strip_suffix("xx") {
+        let class = code(class)?;
+        class * 100..=class * 100 + 99
+    } else if let Some((from, to)) = s.split_once('-') {
+        code(from)?..=code(to)?
+    } else {
+        let c = code(s)?;
+        c..=c
+    };
+    if range.is_empty() || *range.start() < 100 || *range.end() > 999 {
+        return Err(bad());
+    }
+    Ok(range)
+}
+
+impl HttpCheck {
+    pub fn new(srv: &model::Service) -> Result<HttpCheck, String> {
+        let url = Url::parse(&srv.api).map_err(|e| format!("bad url {}: {}", srv.api, e))?;
+        let method = Method::from_bytes(srv.method.to_uppercase().as_bytes())
+            .map_err(|_| format!("bad method {}", srv.method))?;
+        validate_common(srv)?;
+        if srv.body.is_some() && matches!(method, Method::GET | Method::HEAD) {
+            return Err(format!("body is not supported with {}", method));
+        }
+        if srv.auth.is_some()
+            && srv
+                .headers
+                .keys()
+                .any(|k| k.eq_ignore_ascii_case("authorization"))
+        {
+            return Err("auth conflicts with the Authorization header".to_string());
+        }
+        if srv.expect_status.is_empty() {
+            return Err("expect_status is empty".to_string());
+        }
+        if srv.payload.is_some() || srv.banner.is_some() || srv.banner_regex.is_some() {
+            return Err("payload and banner are only supported by tcp checks".to_string());
+        }
+        let mut headers = HeaderMap::new();
+        for (k, v) in &srv.headers {
+            let name =
+                HeaderName::from_bytes(k.as_bytes()).map_err(|_| format!("bad header {}", k))?;
+            let value = HeaderValue::from_str(v).map_err(|_| format!("bad header value {}", k))?;
+            headers.insert(name, value);
+        }
+        let expect_status = srv
+            .expect_status
+            .iter()
+            .map(|s| parse_status(s))
+            .collect::<Result<_, _>>()?;
+        let timeout = Duration::from_secs(srv.timeout_secs);
+        let redirect = match srv.max_redirects {
+            0 => redirect::Policy::none(),
+            n => redirect::Policy::limited(n),
+        };
+        let mut client = Client::builder()
+            .timeout(timeout)
+            .redirect(redirect)
+            .danger_accept_invalid_certs(!srv.tls_verify);
+        let mut tls = native_tls::TlsConnector::builder();
+        tls.danger_accept_invalid_certs(!srv.tls_verify);
+        if let Some(path) = &srv.ca_cert {
+            let pem = fs::read(path).map_err(|e| format!("read ca_cert {} fail: {}", path, e))?;
+            let cert =
+                Certificate::from_pem(&pem).map_err(|e| format!("bad ca_cert {}: {}", path, e))?;
+            client = client.add_root_certificate(cert);
+            let cert = native_tls::Certificate::from_pem(&pem)
+                .map_err(|e| format!("bad ca_cert {}: {}", path, e))?;
+            tls.add_root_certificate(cert);
+        }
+        Ok(HttpCheck {
+            kind: srv.kind,
+            url,
+            method,
+            headers,
+            body: srv.body.clone(),
+            auth: srv.auth.clone(),
+            expect_status,
+            timeout,
+            client: client.build().map_err(|e| e.to_string())?,
+            tls: match srv.probe_phases {
+                true => Some(tls.build().map_err(|e| e.to_string())?.into()),
+                false => None,
+            },
+            latency_warn_ms: srv.latency_warn_ms,
+            latency_error_ms: srv.latency_error_ms,
+            assertions: Assertions::new(srv.assertions.clone())?,
+        })
+    }
+    pub fn request(&self) -> RequestBuilder {
+        let mut req = self
+            .client
+            .request(self.method.clone(), self.url.clone())
+            .headers(self.headers.clone());
+        if let Some(body) = &self.body {
+            req = req.body(body.clone());
+        }
+        match &self.auth {
+            Some(Auth::Basic { username, password }) => req.basic_auth(username, password.as_ref()),
+            Some(Auth::Bearer { token }) => req.bearer_auth(token),
+            None => req,
+        }
+    }
+    pub fn accepts(&self, status: StatusCode) -> bool {
+        self.expect_status
+            .iter()
+            .any(|r| r.contains(&status.as_u16()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_status_codes_and_ranges() {
+        assert_eq!(parse_status("204"), Ok(204..=204));
+        assert_eq!(parse_status("200-299"), Ok(200..=299));
+        assert_eq!(parse_status("3xx"), Ok(300..=399));
+        assert!(parse_status("299-200").is_err());
+        assert!(parse_status("ok").is_err());
+        assert!(parse_status("10xx").is_err());
+    }
+
+    #[test]
+    fn invalid_http_options_fail_at_startup() {
+        let err = |yaml: &str| {
+            let srv: model::Service = serde_yaml::from_str(yaml).unwrap();
+            HttpCheck::new(&srv).err().unwrap()
+        };
+        let api = "name: api, api: 'http://api.local/health'";
+        assert_eq!(
+            err(&format!("{{{}, timeout_secs: 0}}", api)),
+            "timeout_secs must be positive"
+        );
+        assert_eq!(
+            err(&format!(
+                "{{{}, latency_warn_ms: 500, latency_error_ms: 100}}",
+                api
+            )),
+            "latency_warn_ms must not exceed latency_error_ms"
+        );
+        assert_eq!(
+            err(&format!("{{{}, body: x}}", api)),
+            "body is not supported with GET"
+        );
+        assert_eq!(
+            err(&format!(
+                "{{{}, auth: {{kind: bearer, token: t}}, headers: {{authorization: x}}}}",
+                api
+            )),
+            "auth conflicts with the Authorization header"
+        );
+        assert_eq!(
+            err(&format!("{{{}, expect_status: []}}", api)),
+            "expect_status is empty"
+        );
+        assert_eq!(
+            err(&format!("{{{}, method: 'GE T'}}", api)),
+            "bad method GE T"
+        );
+        let srv: model::Service =
+            serde_yaml::from_str(&format!("{{{}, method: post, body: x}}", api)).unwrap();
+        assert!(HttpCheck::new(&srv).is_ok());
+    }
+
+    #[test]
+    fn invalid_tcp_options_fail_at_startup() {
+        let err = |yaml: &str| {
+            let srv: model::Service = serde_yaml::from_str(yaml).unwrap();
+            ServiceCheck::new(&srv).err().unwrap()
+        };
+        let tcp = "name: db, api: 'db:5432', kind: tcp";
+        assert_eq!(
+            err(&format!("{{{}, timeout_secs: 0}}", tcp)),
+            "timeout_secs must be positive"
+        );
+        assert_eq!(
+            err(&format!(
+                "{{{}, latency_warn_ms: 500, latency_error_ms: 100}}",
+                tcp
+            )),
+            "latency_warn_ms must not exceed latency_error_ms"
+        );
+        assert_eq!(
+            err(&format!(
+                "{{{}, method: POST, expect_status: [2xx], assertions: [{{kind: contains, value: x}}]}}",
+                tcp
+            )),
+            "method, expect_status, assertions not supported by tcp checks"
+        );
+        assert_eq!(
+            err(&format!("{{{}, tls_verify: false}}", tcp)),
+            "tls_verify not supported by tcp checks"
+        );
+        assert_eq!(
+            err("{name: api, api: 'http://api.local', banner: ok}"),
+            "payload and banner are only supported by tcp checks"
+        );
+        let srv: model::Service = serde_yaml::from_str(&format!(
+            "{{{}, method: get, payload: PING, banner: PONG}}",
+            tcp
+        ))
+        .unwrap();
+        assert!(ServiceCheck::new(&srv).is_ok());
+    }
+
+    #[test]
+    fn parses_tcp_address() {
+        assert_eq!(parse_address("db:5432"), Ok(("db".to_string(), 5432)));
+        assert_eq!(
+            parse_address("tcp://[::1]:6379"),
+            Ok(("::1".to_string(), 6379))
+        );
+        assert!(parse_address("db").is_err());
+        assert!(parse_address(":22").is_err());
+        assert!(parse_address("db:ssh").is_err());
+    }
+}
@@ -1,16 +1,24 @@
 use tokio::sync::mpsc;
 
 use crate::config::model;
+use crate::core::check::ServiceCheck;
 use crate::core::doctor::*;
 use crate::core::ent::*;
 
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
 // use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 pub struct ServiceChecker {
-    db: Vec<Service>,
+    // 服务的检查配置与上次的检查结果
+    db: Vec<(ServiceCheck, Service)>,
     dc: Doctor,
     tx: mpsc::Sender<Event>,
+    // 最近一次的检查结果 供API查询
+    latest: Arc<RwLock<HashMap<String, Service>>>,
 }
 
 impl ServiceChecker {
@@ -18,17 +26,34 @@ impl ServiceChecker {
         dc: Doctor,
         tx: mpsc::Sender<Event>,
         services: Vec<model::Service>,
-    ) -> ServiceChecker {
+        latest: Arc<RwLock<HashMap<String, Service>>>,
+    ) -> Result<ServiceChecker, String> {
         let mut db = Vec::new();
+        let mut errors = Vec::new();
         for srv in services {
-            db.push(Service {
-                name: String::from(srv.name),
-                api: String::from(srv.api),
+            let check = match ServiceCheck::new(&srv) {
+                Ok(check) => check,
+                Err(e) => {
+                    errors.push(format!("service {}: {}", srv.name, e));
+                    continue;
+                }
+            };
+            let checked = Service {
+                name: srv.name.clone(),
+                api: srv.api.clone(),
                 latency: 0,
+                timings: Timings::default(),
+                components: Vec::new(),
                 last_updated: 0,
-            });
+                status_msg: None,
+                labels: srv.labels.clone(),
+            };
+            db.push((check, checked));
         }
-        ServiceChecker { db, dc, tx }
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
+        }
+        Ok(ServiceChecker { db, dc, tx, latest })
     }
     pub async fn close(&self) {
         self.tx.closed().await;
@@ -36,25 +61,38 @@ impl ServiceChecker {
     pub async fn patrol(&self) {
         //TODO: Graceful Shutdown
         tracing::info!("begin service tranverse check");
-        for srv in &self.db {
+        for (check, srv) in &self.db {
             tracing::info!("service = {:?}", srv);
-            let (status, msg) = self.dc.check_service(&srv.api).await;
-            tracing::info!("check result = {:?} {:?}", status, msg);
+            let (status, msg, timings, components) = match check {
+                ServiceCheck::Http(check) => self.dc.check_service(check).await,
+                ServiceCheck::Tcp(check) => {
+                    let (status, msg, timings) = self.dc.check_tcp(check).await;
+                    (status, msg, timings, Vec::new())
+                }
+            };
+            tracing::info!("check result = {:?} {:?} {:?}", status, msg, timings);
+            let checked = Service {
+                name: String::from(&srv.name),
+                api: String::from(&srv.api),
+                latency: timings.total.unwrap_or(0),
+                timings,
+                components,
+                last_updated: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs(),
+                status_msg: Some(msg),
+                labels: srv.labels.clone(),
+            };
+            self.latest
+                .write()
+                .unwrap()
+                .insert(String::from(&srv.name), checked.clone());
             self.tx
                 .send(Event::Heartbeat(HealthInfo {
-                    target: Target::Service(
-                        String::from(&srv.name),
-                        Some(Service {
-                            name: String::from(&srv.name),
-                            api: String::from(&srv.api),
-                            latency: srv.latency,
-                            last_updated: SystemTime::now()
-                                .duration_since(UNIX_EPOCH)
-                                .unwrap()
-                                .as_secs(),
-                        }),
-                    ),
-                    status: status,
+                    target: Target::Service(String::from(&srv.name), Some(checked)),
+                    status,
+                    alert: None,
                 }))
                 .await
                 .unwrap();
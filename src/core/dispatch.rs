@@ -0,0 +1,815 @@
+use crate::config::model::{AlertMatcher, OnCall, Route, Severity, TargetKind};
+use crate::core::ent::*;
+use crate::core::notifier::Notifier;
+use crate::core::oncall::Escalations;
+use glob::Pattern;
+use std::{
+    cmp,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+};
+
+// 预先编译好的告警匹配条件 路由与静默共用
+#[derive(Debug)]
+pub struct Matcher {
+    severity: Option<Severity>,
+    kind: Option<TargetKind>,
+    name: Option<Pattern>,
+    labels: HashMap<String, String>,
+}
+
+impl Matcher {
+    pub fn new(m: AlertMatcher) -> Result<Matcher, String> {
+        let name = match m.name {
+            Some(name) => {
+                Some(Pattern::new(&name).map_err(|e| format!("bad name glob {}: {}", name, e))?)
+            }
+            None => None,
+        };
+        Ok(Matcher {
+            severity: m.severity,
+            kind: m.kind,
+            name,
+            labels: m.labels,
+        })
+    }
+    pub fn matches(&self, info: &HealthInfo) -> bool {
+        self.matches_as(info, info.status)
+    }
+    // 以status代替事件当前的状态匹配级别 如已恢复的告警按恢复前的级别
+    pub fn matches_as(&self, info: &HealthInfo, status: HealthStatus) -> bool {
+        if let Some(severity) = self.severity {
+            let expected = match severity {
+                Severity::Yellow => HealthStatus::Yellow,
+                Severity::Red => HealthStatus::Red,
+            };
+            if status != expected {
+                return false;
+            }
+        }
+        if let Some(kind) = self.kind {
+            let hit = match &info.target {
+                Target::Node(..) => kind == TargetKind::Node,
+                Target::Service(..) => kind == TargetKind::Service,
+            };
+            if !hit {
+                return false;
+            }
+        }
+        if let Some(name) = &self.name {
+            if !name.matches(info.target.name()) {
+                return false;
+            }
+        }
+        let labels = info.target.labels();
+        self.labels
+            .iter()
+            .all(|(k, v)| labels.and_then(|l| l.get(k)) == Some(v))
+    }
+}
+
+// 子路由未配置时继承上级的选项 receiver为通知渠道下标 None表示全部渠道
+#[derive(Clone, Default)]
+struct RouteOpts {
+    receiver: Option<usize>,
+    escalation: Option<usize>,
+    repeat_secs: u64,
+    group_by: Vec<String>,
+    group_wait: u64,
+    group_interval: u64,
+}
+
+impl RouteOpts {
+    // 告警所属分组的键
+    fn group_key(&self, info: &HealthInfo) -> String {
+        self.group_by
+            .iter()
+            .map(|by| match by.as_str() {
+                "kind" => match info.target {
+                    Target::Node(..) => "node".to_string(),
+                    Target::Service(..) => "service".to_string(),
+                },
+                "severity" => format!("{:?}", info.status),
+                "name" => info.target.name().to_string(),
+                label => info
+                    .target
+                    .labels()
+                    .and_then(|l| l.get(label))
+                    .cloned()
+                    .unwrap_or_default(),
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+// 展开后的路由节点
+struct RouteNode {
+    matcher: Matcher,
+    opts: RouteOpts,
+    continue_matching: bool,
+    children: Vec<usize>,
+}
+
+// 同一路由下待发送的一组告警 同一告警只保留最新的事件
+#[derive(Default)]
+struct Group {
+    events: Vec<HealthInfo>,
+    next_flush: Option<u64>,
+    last_flush: Option<u64>,
+}
+
+// 按升级策略通知中的告警
+struct Escalating {
+    policy: usize,
+    since: u64,
+    next_step: usize,
+    // 确认、静默等期间暂停升级 结束后各级的计时重新开始
+    paused: bool,
+    // 已通知过的渠道 重复通知与恢复时发给它们
+    notified: BTreeSet<usize>,
+}
+
+// 按路由树把告警事件分发给通知渠道 负责分组合并以及firing期间的重复通知
+pub struct Dispatcher {
+    notifiers: Vec<Box<dyn Notifier>>,
+    routes: Vec<RouteNode>,
+    // 告警id -> 已发送过的路由及最近发送时间 恢复时发往同样的路由
+    sent: HashMap<String, HashMap<usize, u64>>,
+    groups: BTreeMap<(usize, String), Group>,
+    escalations: Escalations,
+    // 告警id -> 配置了升级策略的路由及其升级进度
+    escalating: HashMap<String, HashMap<usize, Escalating>>,
+}
+
+impl Dispatcher {
+    // 路由中引用了不存在的渠道或升级策略时返回错误
+    pub fn new(
+        notifiers: Vec<Box<dyn Notifier>>,
+        route: Option<Route>,
+        oncall: OnCall,
+        renotify_secs: u64,
+    ) -> Result<Dispatcher, String> {
+        let mut errors = Vec::new();
+        let names: Vec<&str> = notifiers.iter().map(|n| n.name()).collect();
+        let escalations = Escalations::new(oncall, &names).unwrap_or_else(|e| {
+            errors.push(e);
+            Escalations::default()
+        });
+        let mut dispatcher = Dispatcher {
+            notifiers,
+            routes: Vec::new(),
+            sent: HashMap::new(),
+            groups: BTreeMap::new(),
+            escalations,
+            escalating: HashMap::new(),
+        };
+        let root = RouteOpts {
+            repeat_secs: renotify_secs,
+            ..Default::default()
+        };
+        dispatcher.compile(route.unwrap_or_default(), &root, "route", &mut errors);
+        if errors.is_empty() {
+            Ok(dispatcher)
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+    // 未配置的项继承自parent
+    fn compile(
+        &mut self,
+        route: Route,
+        parent: &RouteOpts,
+        path: &str,
+        errors: &mut Vec<String>,
+    ) -> usize {
+        let receiver = match route.receiver {
+            Some(name) => match self.notifiers.iter().position(|n| n.name() == name) {
+                Some(i) => Some(i),
+                None => {
+                    errors.push(format!("{}: unknown receiver {}", path, name));
+                    parent.receiver
+                }
+            },
+            None => parent.receiver,
+        };
+        let escalation = match route.escalation {
+            Some(name) => self.escalations.find(&name).or_else(|| {
+                errors.push(format!("{}: unknown escalation {}", path, name));
+                parent.escalation
+            }),
+            None => parent.escalation,
+        };
+        let matcher = Matcher::new(route.matcher).unwrap_or_else(|e| {
+            errors.push(format!("{}: {}", path, e));
+            Matcher::new(AlertMatcher::default()).unwrap()
+        });
+        let opts = RouteOpts {
+            receiver,
+            escalation,
+            repeat_secs: route.repeat_interval.unwrap_or(parent.repeat_secs),
+            group_by: route.group_by.unwrap_or_else(|| parent.group_by.clone()),
+            group_wait: route.group_wait.unwrap_or(parent.group_wait),
+            group_interval: route.group_interval.unwrap_or(parent.group_interval),
+        };
+        let idx = self.routes.len();
+        self.routes.push(RouteNode {
+            matcher,
+            opts: opts.clone(),
+            continue_matching: route.continue_matching,
+            children: Vec::new(),
+        });
+        for (i, child) in route.routes.into_iter().enumerate() {
+            let child_path = format!("{}.routes[{}]", path, i);
+            let child = self.compile(child, &opts, &child_path, errors);
+            self.routes[idx].children.push(child);
+        }
+        idx
+    }
+    // 返回告警命中的路由 深度优先 子路由都未命中时由当前路由处理
+    fn find(&self, idx: usize, info: &HealthInfo, out: &mut Vec<usize>) -> bool {
+        let route = &self.routes[idx];
+        if !route.matcher.matches(info) {
+            return false;
+        }
+        let mut matched = false;
+        for &child in &route.children {
+            if self.find(child, info, out) {
+                matched = true;
+                if !self.routes[child].continue_matching {
+                    break;
+                }
+            }
+        }
+        if !matched {
+            out.push(idx);
+        }
+        true
+    }
+    fn receivers(&self, route: usize) -> Vec<usize> {
+        match self.routes[route].opts.receiver {
+            Some(i) => vec![i],
+            None => (0..self.notifiers.len()).collect(),
+        }
+    }
+    // 状态变化的事件 按路由放入各自分组 到期的分组立即发送
+    pub fn dispatch(&mut self, events: Vec<HealthInfo>, now: u64) {
+        for info in events {
+            let Some(alert) = &info.alert else {
+                continue;
+            };
+            let routes = match alert.state {
+                AlertState::Resolved => self
+                    .sent
+                    .remove(&alert.id)
+                    .map(|sent| sent.into_keys().collect())
+                    .unwrap_or_default(),
+                _ => {
+                    let mut routes = Vec::new();
+                    self.find(0, &info, &mut routes);
+                    let sent = self.sent.entry(alert.id.clone()).or_default();
+                    for route in &routes {
+                        sent.insert(*route, now);
+                    }
+                    routes
+                }
+            };
+            for route in routes {
+                self.enqueue(route, &info, now);
+            }
+        }
+        self.flush(now);
+    }
+    // 仍在firing的告警 按各自路由的重复间隔再次发送 quiet的告警(静默、已确认等)跳过
+    pub fn repeat<'a>(
+        &mut self,
+        firing: impl Iterator<Item = &'a HealthInfo>,
+        quiet: impl Fn(&HealthInfo) -> bool,
+        now: u64,
+    ) {
+        let mut alive = HashSet::new();
+        for info in firing {
+            let Some(alert) = &info.alert else {
+                continue;
+            };
+            alive.insert(alert.id.clone());
+            if quiet(info) {
+                continue;
+            }
+            // 触发时处于静默而未发送过的告警 静默结束后按新告警发送
+            if !self.sent.contains_key(&alert.id) {
+                self.dispatch(vec![info.clone()], now);
+                continue;
+            }
+            let sent = self.sent.get_mut(&alert.id).unwrap();
+            let due: Vec<usize> = sent
+                .iter_mut()
+                .filter(|(route, t)| {
+                    let repeat_secs = self.routes[**route].opts.repeat_secs;
+                    repeat_secs > 0 && now.saturating_sub(**t) >= repeat_secs
+                })
+                .map(|(route, t)| {
+                    *t = now;
+                    *route
+                })
+                .collect();
+            if !due.is_empty() {
+                let mut info = info.clone();
+                if let Some(alert) = info.alert.as_mut() {
+                    alert.last_notified = Some(now);
+                }
+                for route in due {
+                    self.enqueue(route, &info, now);
+                }
+            }
+        }
+        // 下线等原因不再跟踪的告警
+        self.sent.retain(|id, _| alive.contains(id));
+        self.flush(now);
+    }
+    // 推进未确认告警的升级 quiet的告警(静默、已确认等)暂停升级
+    pub fn escalate<'a>(
+        &mut self,
+        firing: impl Iterator<Item = &'a HealthInfo>,
+        quiet: impl Fn(&HealthInfo) -> bool,
+        now: u64,
+    ) {
+        let mut alive = HashSet::new();
+        for info in firing {
+            let Some(alert) = &info.alert else {
+                continue;
+            };
+            alive.insert(alert.id.clone());
+            let Some(tracked) = self.escalating.get_mut(&alert.id) else {
+                continue;
+            };
+            if quiet(info) {
+                tracked.values_mut().for_each(|e| e.paused = true);
+                continue;
+            }
+            // 恢复升级时 下一级从现在起按与上一级的间隔计时 避免过期的各级同时通知
+            for e in tracked.values_mut().filter(|e| e.paused) {
+                e.paused = false;
+                e.since = now.saturating_sub(self.escalations.delay(e.policy, e.next_step));
+            }
+            let routes: Vec<usize> = tracked.keys().copied().collect();
+            for route in routes {
+                self.advance(route, info, now);
+            }
+        }
+        self.escalating.retain(|id, _| alive.contains(id));
+    }
+    // 升级策略下的告警不分组 直接通知
+    fn page(&mut self, route: usize, policy: usize, info: &HealthInfo, now: u64) {
+        let Some(alert) = &info.alert else {
+            return;
+        };
+        let tracked = self.escalating.entry(alert.id.clone()).or_default();
+        if alert.state == AlertState::Resolved {
+            let e = tracked.remove(&route);
+            if tracked.is_empty() {
+                self.escalating.remove(&alert.id);
+            }
+            if let Some(e) = e {
+                self.send(&e.notified, vec![info.clone()]);
+            }
+            return;
+        }
+        // 重复通知或级别变化 发给已通知过的渠道
+        if let Some(e) = tracked.get(&route) {
+            let notified = e.notified.clone();
+            self.send(&notified, vec![info.clone()]);
+            return;
+        }
+        tracked.insert(
+            route,
+            Escalating {
+                policy,
+                since: now,
+                next_step: 0,
+                paused: false,
+                notified: BTreeSet::new(),
+            },
+        );
+        self.advance(route, info, now);
+    }
+    // 通知已到期的各级 同一渠道只通知一次
+    fn advance(&mut self, route: usize, info: &HealthInfo, now: u64) {
+        let Some(alert) = &info.alert else {
+            return;
+        };
+        let Some(e) = self
+            .escalating
+            .get_mut(&alert.id)
+            .and_then(|e| e.get_mut(&route))
+        else {
+            return;
+        };
+        let elapsed = now.saturating_sub(e.since);
+        let (next, receivers) = self.escalations.due(e.policy, e.next_step, elapsed, now);
+        if next == e.next_step {
+            return;
+        }
+        tracing::info!("escalate alert {} to step {}", alert.id, next);
+        e.next_step = next;
+        let receivers: Vec<usize> = receivers
+            .into_iter()
+            .filter(|r| e.notified.insert(*r))
+            .collect();
+        self.send(&receivers, vec![info.clone()]);
+    }
+    fn send<'a>(&self, receivers: impl IntoIterator<Item = &'a usize>, events: Vec<HealthInfo>) {
+        for &i in receivers {
+            let notifier = &self.notifiers[i];
+            tracing::info!("notify {} events via {}", events.len(), notifier.name());
+            notifier.notify(events.clone());
+        }
+    }
+    // 新分组等待group_wait 已发送过的分组距上次发送至少group_interval
+    fn enqueue(&mut self, route: usize, info: &HealthInfo, now: u64) {
+        if let Some(policy) = self.routes[route].opts.escalation {
+            self.page(route, policy, info, now);
+            return;
+        }
+        let opts = &self.routes[route].opts;
+        let group = self
+            .groups
+            .entry((route, opts.group_key(info)))
+            .or_default();
+        let id = info.alert.as_ref().map(|a| &a.id);
+        group
+            .events
+            .retain(|e| e.alert.as_ref().map(|a| &a.id) != id);
+        group.events.push(info.clone());
+        if group.next_flush.is_none() {
+            group.next_flush = Some(match group.last_flush {
+                Some(last) => cmp::max(now, last + opts.group_interval),
+                None => now + opts.group_wait,
+            });
+        }
+    }
+    // 发送到期的分组 每个分组对每个渠道发送一次通知
+    pub fn flush(&mut self, now: u64) {
+        let mut batches = Vec::new();
+        for ((route, _), group) in self.groups.iter_mut() {
+            if group.next_flush.is_some_and(|t| t <= now) {
+                group.next_flush = None;
+                group.last_flush = Some(now);
+                batches.push((*route, std::mem::take(&mut group.events)));
+            }
+        }
+        // 空闲的分组过了group_interval即可丢弃 之后按新分组处理
+        let routes = &self.routes;
+        self.groups.retain(|(route, _), group| {
+            group.next_flush.is_some()
+                || group
+                    .last_flush
+                    .is_some_and(|t| now < t + routes[*route].opts.group_interval)
+        });
+        for (route, events) in batches {
+            self.send(&self.receivers(route), events);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{ack::AckSigner, alert::AlertBook};
+    use std::sync::{Arc, Mutex};
+
+    type Sent = Arc<Mutex<Vec<(String, Vec<String>)>>>;
+
+    struct Recorder {
+        name: String,
+        sent: Sent,
+    }
+
+    impl Notifier for Recorder {
+        fn name(&self) -> &str {
+            &self.name
+        }
+        fn notify(&self, events: Vec<HealthInfo>) {
+            let names = events.iter().map(|e| e.target.key()).collect();
+            self.sent.lock().unwrap().push((self.name.clone(), names));
+        }
+    }
+
+    fn dispatcher(sent: &Sent) -> Dispatcher {
+        let notifiers = ["mail", "pager", "chat"]
+            .iter()
+            .map(|name| {
+                Box::new(Recorder {
+                    name: name.to_string(),
+                    sent: sent.clone(),
+                }) as Box<dyn Notifier>
+            })
+            .collect();
+        let route: Route = serde_yaml::from_str(
+            r#"
+receiver: mail
+routes:
+  - match: {severity: Red, kind: node}
+    receiver: pager
+    repeat_interval: 60
+    continue: true
+  - match: {name: "db-*"}
+  - match: {labels: {team: partner}}
+    receiver: chat
+"#,
+        )
+        .unwrap();
+        Dispatcher::new(notifiers, Some(route), OnCall::default(), 3600).unwrap()
+    }
+
+    fn alert(target: Target, status: HealthStatus, state: AlertState) -> HealthInfo {
+        HealthInfo {
+            alert: Some(Alert {
+                id: target.key(),
+                rule: "health".to_string(),
+                state,
+                since: 0,
+                last_notified: None,
+                ack_url: None,
+                acked_by: None,
+                acked_at: None,
+            }),
+            target,
+            status,
+        }
+    }
+
+    fn take(sent: &Sent) -> Vec<(String, Vec<String>)> {
+        let mut sent = std::mem::take(&mut *sent.lock().unwrap());
+        sent.sort();
+        sent
+    }
+
+    #[test]
+    fn routes_by_severity_kind_name_and_labels() {
+        let sent: Sent = Arc::default();
+        let mut dc = dispatcher(&sent);
+        let db = Target::Node("db-1".to_string(), None);
+        let partner = Target::Service(
+            "api".to_string(),
+            Some(Service {
+                name: "api".to_string(),
+                api: "http://api".to_string(),
+                latency: 0,
+                timings: Timings::default(),
+                components: Vec::new(),
+                last_updated: 0,
+                status_msg: None,
+                labels: HashMap::from([("team".to_string(), "partner".to_string())]),
+            }),
+        );
+        dc.dispatch(
+            vec![
+                alert(db.clone(), HealthStatus::Red, AlertState::Firing),
+                alert(partner.clone(), HealthStatus::Yellow, AlertState::Firing),
+            ],
+            0,
+        );
+        // red节点命中pager后继续匹配 db-*落到根路由的mail
+        assert_eq!(
+            take(&sent),
+            vec![
+                ("chat".to_string(), vec!["service/api".to_string()]),
+                ("mail".to_string(), vec!["node/db-1".to_string()]),
+                ("pager".to_string(), vec!["node/db-1".to_string()]),
+            ]
+        );
+
+        // 只有pager的重复间隔已到
+        let firing = [
+            alert(db.clone(), HealthStatus::Red, AlertState::Firing),
+            alert(partner, HealthStatus::Yellow, AlertState::Firing),
+        ];
+        dc.repeat(firing.iter(), |_| false, 30);
+        assert!(take(&sent).is_empty());
+        dc.repeat(firing.iter(), |_| false, 60);
+        assert_eq!(
+            take(&sent),
+            vec![("pager".to_string(), vec!["node/db-1".to_string()])]
+        );
+
+        // 恢复时status为Green 仍发往触发时的路由
+        dc.dispatch(
+            vec![alert(db, HealthStatus::Green, AlertState::Resolved)],
+            90,
+        );
+        assert_eq!(
+            take(&sent),
+            vec![
+                ("mail".to_string(), vec!["node/db-1".to_string()]),
+                ("pager".to_string(), vec!["node/db-1".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn groups_alerts_by_key_with_wait_and_interval() {
+        let sent: Sent = Arc::default();
+        let recorder = Box::new(Recorder {
+            name: "mail".to_string(),
+            sent: sent.clone(),
+        }) as Box<dyn Notifier>;
+        let route: Route = serde_yaml::from_str(
+            "{receiver: mail, group_by: [kind], group_wait: 30, group_interval: 60}",
+        )
+        .unwrap();
+        let mut dc = Dispatcher::new(vec![recorder], Some(route), OnCall::default(), 0).unwrap();
+        let node = |id: &str| {
+            alert(
+                Target::Node(id.to_string(), None),
+                HealthStatus::Red,
+                AlertState::Firing,
+            )
+        };
+        let service = alert(
+            Target::Service("api".to_string(), None),
+            HealthStatus::Red,
+            AlertState::Firing,
+        );
+        dc.dispatch(vec![node("n1"), node("n2"), service], 0);
+        dc.dispatch(vec![node("n3")], 10);
+        dc.flush(29);
+        assert!(take(&sent).is_empty());
+        // 同组的告警合并为一次通知
+        dc.flush(30);
+        assert_eq!(
+            take(&sent),
+            vec![
+                (
+                    "mail".to_string(),
+                    vec![
+                        "node/n1".to_string(),
+                        "node/n2".to_string(),
+                        "node/n3".to_string()
+                    ]
+                ),
+                ("mail".to_string(), vec!["service/api".to_string()]),
+            ]
+        );
+        // 已发送过的分组需等待group_interval
+        dc.dispatch(vec![node("n4")], 40);
+        dc.flush(89);
+        assert!(take(&sent).is_empty());
+        dc.flush(90);
+        assert_eq!(
+            take(&sent),
+            vec![("mail".to_string(), vec!["node/n4".to_string()])]
+        );
+    }
+
+    #[test]
+    fn alert_fired_while_silenced_is_sent_once_repeated() {
+        let sent: Sent = Arc::default();
+        let mut dc = dispatcher(&sent);
+        let web = alert(
+            Target::Node("web-1".to_string(), None),
+            HealthStatus::Yellow,
+            AlertState::Firing,
+        );
+        // 触发时被静默 dispatch未收到该告警 静默结束后的repeat按新告警发送
+        dc.repeat([web.clone()].iter(), |_| false, 100);
+        assert_eq!(
+            take(&sent),
+            vec![("mail".to_string(), vec!["node/web-1".to_string()])]
+        );
+        dc.repeat([web].iter(), |_| false, 200);
+        assert!(take(&sent).is_empty());
+    }
+
+    #[test]
+    fn escalates_until_acked_and_resolves_to_all_notified() {
+        let sent: Sent = Arc::default();
+        let notifiers = ["alice", "bob", "lead"]
+            .iter()
+            .map(|name| {
+                Box::new(Recorder {
+                    name: name.to_string(),
+                    sent: sent.clone(),
+                }) as Box<dyn Notifier>
+            })
+            .collect();
+        let route: Route = serde_yaml::from_str("escalation: db").unwrap();
+        let oncall: OnCall = serde_yaml::from_str(
+            r#"
+escalations:
+  - name: db
+    steps:
+      - {receiver: alice}
+      - {receiver: bob, delay_secs: 600}
+      - {receiver: lead, delay_secs: 1800}
+"#,
+        )
+        .unwrap();
+        let mut dc = Dispatcher::new(notifiers, Some(route), oncall, 0).unwrap();
+        let notified = |name: &str| vec![(name.to_string(), vec!["node/db-1".to_string()])];
+        let mut db = alert(
+            Target::Node("db-1".to_string(), None),
+            HealthStatus::Red,
+            AlertState::Firing,
+        );
+        dc.dispatch(vec![db.clone()], 100);
+        assert_eq!(take(&sent), notified("alice"));
+        dc.escalate([db.clone()].iter(), |_| false, 699);
+        assert!(take(&sent).is_empty());
+        dc.escalate([db.clone()].iter(), |_| false, 700);
+        assert_eq!(take(&sent), notified("bob"));
+        // 确认后不再升级
+        dc.escalate([db.clone()].iter(), |_| true, 1900);
+        assert!(take(&sent).is_empty());
+
+        db.alert.as_mut().unwrap().state = AlertState::Resolved;
+        dc.dispatch(vec![db], 2000);
+        let mut both = notified("alice");
+        both.extend(notified("bob"));
+        assert_eq!(take(&sent), both);
+        assert!(dc.escalating.is_empty());
+    }
+
+    #[test]
+    fn escalation_restarts_after_ack_expires() {
+        let sent: Sent = Arc::default();
+        let notifiers = ["alice", "bob", "lead"]
+            .iter()
+            .map(|name| {
+                Box::new(Recorder {
+                    name: name.to_string(),
+                    sent: sent.clone(),
+                }) as Box<dyn Notifier>
+            })
+            .collect();
+        let route: Route = serde_yaml::from_str("escalation: db").unwrap();
+        let oncall: OnCall = serde_yaml::from_str(
+            r#"
+escalations:
+  - name: db
+    steps:
+      - {receiver: alice}
+      - {receiver: bob, delay_secs: 600}
+      - {receiver: lead, delay_secs: 1800}
+"#,
+        )
+        .unwrap();
+        let mut dc = Dispatcher::new(notifiers, Some(route), oncall, 0).unwrap();
+        let notified = |name: &str| vec![(name.to_string(), vec!["service/db".to_string()])];
+        let mut book = AlertBook::new(AckSigner::default(), 3000, None);
+        let fired = book
+            .update(
+                HealthInfo {
+                    target: Target::Service("db".to_string(), None),
+                    status: HealthStatus::Red,
+                    alert: None,
+                },
+                "health",
+                false,
+                100,
+            )
+            .unwrap();
+        let id = fired.alert.as_ref().unwrap().id.clone();
+        dc.dispatch(vec![fired], 100);
+        assert_eq!(take(&sent), notified("alice"));
+        let acked = |h: &HealthInfo| h.alert.as_ref().is_some_and(|a| a.acked_by.is_some());
+
+        book.ack(&id, "alice", 200).unwrap();
+        for now in [300, 1000, 3000] {
+            book.expire_acks(now);
+            dc.escalate(book.firing(), acked, now);
+        }
+        assert!(take(&sent).is_empty());
+        // 确认过期后 bob与lead不会同时收到通知
+        book.expire_acks(3200);
+        dc.escalate(book.firing(), acked, 3200);
+        assert!(take(&sent).is_empty());
+        dc.escalate(book.firing(), acked, 3799);
+        assert!(take(&sent).is_empty());
+        dc.escalate(book.firing(), acked, 3800);
+        assert_eq!(take(&sent), notified("bob"));
+        dc.escalate(book.firing(), acked, 4999);
+        assert!(take(&sent).is_empty());
+        dc.escalate(book.firing(), acked, 5000);
+        assert_eq!(take(&sent), notified("lead"));
+    }
+
+    #[test]
+    fn unknown_receiver_fails_at_startup() {
+        let route = Route {
+            receiver: Some("nobody".to_string()),
+            ..Default::default()
+        };
+        let err = Dispatcher::new(Vec::new(), Some(route), OnCall::default(), 0)
+            .err()
+            .unwrap();
+        assert!(err.contains("unknown receiver nobody"));
+        let route = Route {
+            escalation: Some("nobody".to_string()),
+            ..Default::default()
+        };
+        let err = Dispatcher::new(Vec::new(), Some(route), OnCall::default(), 0)
+            .err()
+            .unwrap();
+        assert!(err.contains("unknown escalation nobody"));
+    }
+}
@@ -1,80 +1,995 @@
+use crate::config::model::{CheckKind, Comparison, Metric, NodeOverride, Rule};
+use crate::core::check::{HttpCheck, TcpCheck};
 use crate::core::ent::*;
-use reqwest::{Client, StatusCode};
+use crate::core::window::NodeWindows;
+use crate::core::{payload, probe};
+use glob::Pattern;
+use ipnet::IpNet;
 use std::{
     cmp,
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, RwLock},
+    time::{Instant, SystemTime, UNIX_EPOCH},
 };
-//TODO: 由配置文件加载的节点健康状态判断
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{lookup_host, TcpStream},
+    time::timeout_at,
+};
+// 服务检查读取响应体的上限
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+// 节点健康状态按配置文件加载的规则判断
 #[derive(Debug, Clone)]
 pub struct Doctor {
-    client: Client,
+    rules: Vec<Rule>,
+    groups: Vec<NodeGroup>,
+    // 通过API为单个节点设置的阈值 所有Doctor副本共享
+    thresholds: Arc<RwLock<HashMap<String, Vec<Rule>>>>,
+}
+
+// 预先编译好匹配条件的节点组
+#[derive(Debug, Clone)]
+struct NodeGroup {
+    hostname: Option<Pattern>,
+    cidr: Option<IpNet>,
+    labels: HashMap<String, String>,
+    rules: Vec<Rule>,
+}
+
+impl NodeGroup {
+    fn new(o: NodeOverride) -> Result<NodeGroup, String> {
+        let hostname = o
+            .matcher
+            .hostname
+            .map(|h| Pattern::new(&h).map_err(|e| format!("bad hostname glob {}: {}", h, e)))
+            .transpose()?;
+        let cidr = o
+            .matcher
+            .cidr
+            .map(|c| c.parse().map_err(|e| format!("bad cidr {}: {}", c, e)))
+            .transpose()?;
+        Ok(NodeGroup {
+            hostname,
+            cidr,
+            labels: o.matcher.labels,
+            rules: o.rules,
+        })
+    }
+    fn matches(&self, node: &Node) -> bool {
+        if let Some(hostname) = &self.hostname {
+            if !hostname.matches(&node.id) {
+                return false;
+            }
+        }
+        if let Some(cidr) = &self.cidr {
+            match node.system_ip.parse::<IpAddr>() {
+                Ok(ip) if cidr.contains(&ip) => {}
+                _ => return false,
+            }
+        }
+        self.labels
+            .iter()
+            .all(|(k, v)| node.labels.get(k) == Some(v))
+    }
+}
+
+// 用rules中的规则替换base里同一指标的规则
+fn overlay(base: &mut Vec<Rule>, rules: &[Rule]) {
+    for rule in rules {
+        base.retain(|r| r.metric != rule.metric);
+    }
+    base.extend(rules.iter().cloned());
 }
 
 impl Doctor {
-    pub fn new() -> Doctor {
-        Doctor {
-            client: Client::builder()
-                .timeout(Duration::from_secs(3))
-                .build()
-                .unwrap(),
+    // 节点组的匹配条件有误时返回全部错误
+    pub fn new(rules: Vec<Rule>, overrides: Vec<NodeOverride>) -> Result<Doctor, String> {
+        let mut groups = Vec::new();
+        let mut errors = Vec::new();
+        for o in overrides {
+            let name = o.name.clone();
+            match NodeGroup::new(o) {
+                Ok(group) => groups.push(group),
+                Err(e) => errors.push(format!("override {}: {}", name, e)),
+            }
         }
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
+        }
+        Ok(Doctor {
+            rules,
+            groups,
+            thresholds: Arc::new(RwLock::new(HashMap::new())),
+        })
+    }
+    // 节点实际生效的规则: 全局规则 < 匹配的节点组(按配置顺序) < API设置的阈值
+    pub fn rules_for(&self, node: &Node) -> Vec<Rule> {
+        let mut rules = self.rules.clone();
+        for group in self.groups.iter().filter(|g| g.matches(node)) {
+            overlay(&mut rules, &group.rules);
+        }
+        if let Some(custom) = self.thresholds.read().unwrap().get(&node.id) {
+            overlay(&mut rules, custom);
+        }
+        rules
     }
+    pub fn thresholds(&self, id: &str) -> Option<Vec<Rule>> {
+        self.thresholds.read().unwrap().get(id).cloned()
+    }
+    pub fn set_thresholds(&self, id: String, rules: Vec<Rule>) {
+        self.thresholds.write().unwrap().insert(id, rules);
+    }
+    pub fn remove_thresholds(&self, id: &str) -> Option<Vec<Rule>> {
+        self.thresholds.write().unwrap().remove(id)
+    }
+    // 只看本次采样 不考虑持续时长
     pub fn check_node(&self, node: &Node) -> (HealthStatus, String) {
-        let mut level = 0;
-        let mut msg = String::from("");
-        //条件1 硬盘占用率
-        if node.disk_per >= 90 {
-            level = 2;
-            msg.push_str("Error: disk > 90% .\n");
-        } else if node.disk_per >= 70 {
-            level = 1;
-            msg.push_str("Warn: disk > 70% .\n");
-        }
-        //条件2 内存占用率
-        if node.mem_status_per >= 90 {
-            level = cmp::max(level, 2);
-            msg.push_str("Error: mem > 90% .\n");
-        } else if node.mem_status_per >= 70 {
-            level = cmp::max(level, 1);
-            msg.push_str("Warn: mem > 70% .\n");
-        }
-        //条件3 节点上次更新时间
+        let (status, msg, _) = self.diagnose(node, None);
+        (status, msg)
+    }
+    // 结合节点的采样窗口判断 条件需持续满足才触发 越过恢复阈值才解除
+    // 同时返回命中的指标 用于区分告警
+    pub fn check_node_sustained(
+        &self,
+        node: &Node,
+        windows: &mut NodeWindows,
+    ) -> (HealthStatus, String, Vec<Metric>) {
+        self.diagnose(node, Some(windows))
+    }
+    fn diagnose(
+        &self,
+        node: &Node,
+        mut windows: Option<&mut NodeWindows>,
+    ) -> (HealthStatus, String, Vec<Metric>) {
         let cur_time = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_secs();
-        if cur_time - node.last_updated > 1200 {
-            // 暂时不做：先检查一下服务器上端口的健康状态
-            // 如果正常 则仅记录本次为节点监控失效
-            // 如果不正常 则发出警告: 节点和服务均下线
-            level = cmp::max(level, 2);
-            msg.push_str("Error: node hasn't update for 20 min.")
-        } else if cur_time - node.last_updated > 600 {
-            level = cmp::max(level, 1);
-            msg.push_str("Warn: node hasn't update for 10 min.")
+        let mut level = 0;
+        let mut msgs: Vec<String> = Vec::new();
+        let mut hits = Vec::new();
+        for rule in &self.rules_for(node) {
+            let value = measure(rule.metric, node, cur_time);
+            let mut lv = rule.level(value);
+            if let Some(windows) = windows.as_deref_mut() {
+                // 超时规则每次检查都是一次新采样 其余指标以节点上报时间区分采样
+                let ts = match rule.metric {
+                    Metric::StaleSecs => cur_time,
+                    _ => node.last_updated,
+                };
+                let for_secs = rule.for_secs.unwrap_or(0);
+                let for_samples = rule.for_samples.unwrap_or(1);
+                let window = windows.entry(rule.metric).or_default();
+                window.push(ts, lv, for_secs, for_samples);
+                let fired = [2, 1]
+                    .into_iter()
+                    .find(|l| window.sustained(*l, for_secs, for_samples))
+                    .unwrap_or(0);
+                let kept = (1..=window.held())
+                    .rev()
+                    .find(|l| !rule.recovered(*l, value))
+                    .unwrap_or(0);
+                lv = cmp::max(fired, kept);
+                window.hold(lv);
+            }
+            if lv > 0 {
+                level = cmp::max(level, lv);
+                msgs.push(rule.message(lv, value));
+                hits.push(rule.metric);
+            }
         }
         match level {
-            0 => (HealthStatus::Green, "everything looks fine".to_string()),
-            1 => (HealthStatus::Yellow, msg),
-            _ => (HealthStatus::Red, msg),
+            0 => (
+                HealthStatus::Green,
+                "everything looks fine".to_string(),
+                hits,
+            ),
+            1 => (HealthStatus::Yellow, msgs.join("\n"), hits),
+            _ => (HealthStatus::Red, msgs.join("\n"), hits),
         }
     }
-    pub async fn check_service(&self, url: &String) -> (HealthStatus, String) {
-        let resp = self.client.get(url).send().await;
-        match resp {
+    // 同时返回各阶段的耗时与依赖组件的状态 响应状态码符合预期时再检查响应内容与总耗时
+    // 开启probe_phases时请求前单独测量各阶段 与请求共用timeout 目标不可达时最多等待一次timeout
+    // 结构化的健康接口在依赖异常时多返回503 能解析出状态时不看状态码
+    pub async fn check_service(
+        &self,
+        check: &HttpCheck,
+    ) -> (HealthStatus, String, Timings, Vec<Component>) {
+        let deadline = tokio::time::Instant::now() + check.timeout;
+        let probed = match &check.tls {
+            Some(tls) => probe::phases(&check.url, deadline, tls).await,
+            None => Ok(Timings::default()),
+        };
+        let mut timings = match probed {
+            Ok(timings) => timings,
+            Err((timings, err)) => {
+                return (
+                    HealthStatus::Red,
+                    format!("Error: service({}) {}", check.url, err),
+                    timings,
+                    Vec::new(),
+                )
+            }
+        };
+        let start = Instant::now();
+        let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+        // 只有断言与结构化的健康接口需要响应体 超过MAX_BODY_BYTES的部分截断 不影响检查结果
+        let limit = match check.kind {
+            CheckKind::Actuator | CheckKind::HealthJson => Some(MAX_BODY_BYTES),
+            _ => check
+                .assertions
+                .body_limit()
+                .map(|limit| limit.min(MAX_BODY_BYTES)),
+        };
+        let resp = match check.request().timeout(remaining).send().await {
             Ok(resp) => {
-                if resp.status() == StatusCode::OK {
-                    return (HealthStatus::Green, "success".to_string());
+                let code = resp.status();
+                match limit {
+                    Some(limit) => read_body(resp, limit).await.map(|body| (code, body)),
+                    None => Ok((code, Vec::new())),
                 }
-                (
-                    HealthStatus::Yellow,
-                    "Warn: service resp statuscode not 200".to_string(),
+            }
+            Err(err) => Err(err),
+        };
+        let (code, body) = match resp {
+            Ok(resp) => resp,
+            Err(err) => {
+                return (
+                    HealthStatus::Red,
+                    format!("Error: service({}) request fail {:?}", check.url, err),
+                    timings,
+                    Vec::new(),
                 )
             }
-            Err(err) => (
-                HealthStatus::Red,
-                format!("Error: service({:?}) get fail {:?}", url, err),
+        };
+        let total = start.elapsed().as_millis();
+        timings.total = Some(total);
+        let parsed = payload::parse(check.kind, &body);
+        if parsed.is_none() && !check.accepts(code) {
+            return (
+                HealthStatus::Yellow,
+                format!("Warn: service resp statuscode {} not expected", code),
+                timings,
+                Vec::new(),
+            );
+        }
+        let (mut status, mut msgs) = check.assertions.check(&body);
+        let mut components = Vec::new();
+        if let Some((overall, parts)) = parsed {
+            let mut failed: Vec<&Component> = parts
+                .iter()
+                .filter(|c| c.health != HealthStatus::Green)
+                .collect();
+            if failed.is_empty() && overall.health != HealthStatus::Green {
+                failed.push(&overall);
+            }
+            for c in failed {
+                let prefix = match c.health {
+                    HealthStatus::Red => "Error",
+                    _ => "Warn",
+                };
+                let name = if c.name.is_empty() {
+                    "service"
+                } else {
+                    &c.name
+                };
+                let mut msg = format!("{}: {} is {}", prefix, name, c.status);
+                if let Some(detail) = &c.detail {
+                    msg.push_str(&format!(": {}", detail));
+                }
+                msgs.push(msg);
+            }
+            status = worse(status, overall.health);
+            for c in &parts {
+                status = worse(status, c.health);
+            }
+            components = parts;
+        }
+        let (latency, msg) = check_latency(
+            "service responded",
+            check.latency_warn_ms,
+            check.latency_error_ms,
+            total,
+        );
+        status = worse(status, latency);
+        if latency != HealthStatus::Green || msgs.is_empty() {
+            msgs.push(msg);
+        }
+        (status, msgs.join("\n"), timings, components)
+    }
+    // 连接失败或banner不符时为Red 耗时阈值按连接耗时判断
+    pub async fn check_tcp(&self, check: &TcpCheck) -> (HealthStatus, String, Timings) {
+        let mut timings = Timings::default();
+        let addr = check.address();
+        let fail = |msg: String, timings| (HealthStatus::Red, format!("Error: {}", msg), timings);
+        // 解析、连接、发送与读取banner共用一个截止时间 整个检查最多耗时一次timeout
+        let deadline = tokio::time::Instant::now() + check.timeout;
+        let start = Instant::now();
+        let resolved =
+            match timeout_at(deadline, lookup_host((check.host.as_str(), check.port))).await {
+                Ok(Ok(mut addrs)) => addrs.next(),
+                Ok(Err(err)) => return fail(format!("resolve {} fail: {}", addr, err), timings),
+                Err(_) => return fail(format!("resolve {} timeout", addr), timings),
+            };
+        timings.dns = Some(start.elapsed().as_millis());
+        let Some(resolved) = resolved else {
+            return fail(format!("resolve {} fail: no address", addr), timings);
+        };
+        let start = Instant::now();
+        let mut stream = match timeout_at(deadline, TcpStream::connect(resolved)).await {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(err)) => return fail(format!("connect {} fail: {}", addr, err), timings),
+            Err(_) => return fail(format!("connect {} timeout", addr), timings),
+        };
+        let connect = start.elapsed().as_millis();
+        timings.connect = Some(connect);
+        timings.total = Some(connect);
+        if let Some(payload) = &check.payload {
+            match timeout_at(deadline, stream.write_all(payload)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => return fail(format!("send to {} fail: {}", addr, err), timings),
+                Err(_) => return fail(format!("send to {} timeout", addr), timings),
+            }
+        }
+        if check.expects_banner() {
+            let banner = read_banner(&mut stream, check, deadline).await;
+            if !check.matches(&banner) {
+                return fail(
+                    format!("{} banner not matched, got {:?}", addr, banner),
+                    timings,
+                );
+            }
+        }
+        let (status, msg) = check_latency(
+            "connected",
+            check.latency_warn_ms,
+            check.latency_error_ms,
+            connect,
+        );
+        (status, msg, timings)
+    }
+}
+
+// banner可能分多次到达 读到匹配的内容、连接关闭、超时或超过上限为止
+async fn read_banner(
+    stream: &mut TcpStream,
+    check: &TcpCheck,
+    deadline: tokio::time::Instant,
+) -> String {
+    const LIMIT: usize = 64 * 1024;
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    while buf.len() < LIMIT {
+        match timeout_at(deadline, stream.read(&mut chunk)).await {
+            Ok(Ok(n)) if n > 0 => buf.extend_from_slice(&chunk[..n]),
+            _ => break,
+        }
+        if check.matches(&String::from_utf8_lossy(&buf)) {
+            break;
+        }
+    }
+    String::from_utf8_lossy(&buf).into_owned()
+}
+
+// 读取至多limit字节的响应体
+async fn read_body(mut resp: reqwest::Response, limit: usize) -> reqwest::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    while let Some(chunk) = resp.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() >= limit {
+            body.truncate(limit);
+            break;
+        }
+    }
+    Ok(body)
+}
+
+fn worse(a: HealthStatus, b: HealthStatus) -> HealthStatus {
+    if level(b) > level(a) {
+        b
+    } else {
+        a
+    }
+}
+
+fn level(status: HealthStatus) -> u8 {
+    match status {
+        HealthStatus::Green => 0,
+        HealthStatus::Yellow => 1,
+        HealthStatus::Red => 2,
+    }
+}
+
+// what说明耗时的含义 如http的响应耗时与tcp的连接耗时
+fn check_latency(
+    what: &str,
+    warn: Option<u128>,
+    error: Option<u128>,
+    total: u128,
+) -> (HealthStatus, String) {
+    if let Some(error) = error.filter(|ms| total >= *ms) {
+        return (
+            HealthStatus::Red,
+            format!("Error: {} in {}ms, over {}ms", what, total, error),
+        );
+    }
+    if let Some(warn) = warn.filter(|ms| total >= *ms) {
+        return (
+            HealthStatus::Yellow,
+            format!("Warn: {} in {}ms, over {}ms", what, total, warn),
+        );
+    }
+    (HealthStatus::Green, format!("success in {}ms", total))
+}
+
+// 取出节点对应指标的数值
+fn measure(metric: Metric, node: &Node, cur_time: u64) -> f64 {
+    match metric {
+        Metric::DiskPer => node.disk_per as f64,
+        Metric::MemStatusPer => node.mem_status_per as f64,
+        Metric::Load1 => node.load_1 as f64,
+        Metric::Load5 => node.load_5 as f64,
+        Metric::Load15 => node.load_15 as f64,
+        Metric::StaleSecs => cur_time.saturating_sub(node.last_updated) as f64,
+    }
+}
+
+impl Comparison {
+    pub fn hit(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Comparison::Gt => value > threshold,
+            Comparison::Ge => value >= threshold,
+            Comparison::Lt => value < threshold,
+            Comparison::Le => value <= threshold,
+        }
+    }
+    fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+        }
+    }
+}
+
+impl Rule {
+    // 命中的级别 0 正常 1 警告 2 错误
+    fn level(&self, value: f64) -> u8 {
+        if self.error.is_some_and(|t| self.op.hit(value, t)) {
+            2
+        } else if self.warn.is_some_and(|t| self.op.hit(value, t)) {
+            1
+        } else {
+            0
+        }
+    }
+    // 已触发的级别是否已越过恢复阈值
+    fn recovered(&self, level: u8, value: f64) -> bool {
+        let threshold = match level {
+            2 => self.error_recover.or(self.error),
+            _ => self.warn_recover.or(self.warn),
+        };
+        threshold.is_none_or(|t| !self.op.hit(value, t))
+    }
+    fn message(&self, level: u8, value: f64) -> String {
+        let (name, template, threshold) = match level {
+            2 => ("Error", &self.error_msg, self.error),
+            _ => ("Warn", &self.warn_msg, self.warn),
+        };
+        let threshold = threshold.unwrap_or_default();
+        let metric = self.metric.name();
+        match template {
+            Some(template) => template
+                .replace("{metric}", metric)
+                .replace("{value}", &value.to_string())
+                .replace("{threshold}", &threshold.to_string()),
+            None => format!(
+                "{}: {} {} {} (current {}) .",
+                name,
+                metric,
+                self.op.symbol(),
+                threshold,
+                value
             ),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::model::{default_rules, NodeMatcher};
+
+    fn node(disk_per: u32, mem_status_per: u32, last_updated: u64) -> Node {
+        Node {
+            id: "node-1".to_string(),
+            time_day: String::new(),
+            system_ip: "10.0.0.1".to_string(),
+            load_1: 0,
+            load_5: 0,
+            load_15: 0.0,
+            mem_status_total: String::new(),
+            mem_status_use: String::new(),
+            mem_status_per,
+            mem_status: String::new(),
+            disk_f: String::new(),
+            disk_total: String::new(),
+            disk_free: String::new(),
+            disk_per,
+            disk_f_60: String::new(),
+            disk_per_60: String::new(),
+            disk_status: String::new(),
+            last_updated,
+            status_msg: None,
+            labels: HashMap::new(),
+        }
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[test]
+    fn default_rules_keep_legacy_thresholds() {
+        let dc = Doctor::new(default_rules(), vec![]).unwrap();
+        let (status, _) = dc.check_node(&node(10, 10, now()));
+        assert!(matches!(status, HealthStatus::Green));
+        let (status, msg) = dc.check_node(&node(75, 10, now()));
+        assert!(matches!(status, HealthStatus::Yellow));
+        assert_eq!(msg, "Warn: disk > 70% .");
+        let (status, _) = dc.check_node(&node(10, 95, now()));
+        assert!(matches!(status, HealthStatus::Red));
+        let (status, _) = dc.check_node(&node(10, 10, now() - 1300));
+        assert!(matches!(status, HealthStatus::Red));
+    }
+
+    #[test]
+    fn custom_rule_renders_template() {
+        let dc = Doctor::new(
+            vec![Rule {
+                metric: Metric::MemStatusPer,
+                op: Comparison::Gt,
+                warn: None,
+                error: Some(95.0),
+                warn_msg: None,
+                error_msg: Some("{metric} at {value}% over {threshold}%".to_string()),
+                for_secs: None,
+                for_samples: None,
+                warn_recover: None,
+                error_recover: None,
+            }],
+            vec![],
+        )
+        .unwrap();
+        let (status, _) = dc.check_node(&node(99, 90, now()));
+        assert!(matches!(status, HealthStatus::Green));
+        let (status, msg) = dc.check_node(&node(10, 97, now()));
+        assert!(matches!(status, HealthStatus::Red));
+        assert_eq!(msg, "mem_status_per at 97% over 95%");
+    }
+
+    fn mem_rule(warn: f64, error: f64) -> Rule {
+        Rule {
+            metric: Metric::MemStatusPer,
+            op: Comparison::Ge,
+            warn: Some(warn),
+            error: Some(error),
+            warn_msg: None,
+            error_msg: None,
+            for_secs: None,
+            for_samples: None,
+            warn_recover: None,
+            error_recover: None,
+        }
+    }
+
+    #[test]
+    fn overrides_apply_by_hostname_cidr_and_label() {
+        let dc = Doctor::new(
+            default_rules(),
+            vec![
+                NodeOverride {
+                    name: "db".to_string(),
+                    matcher: NodeMatcher {
+                        hostname: Some("db-*".to_string()),
+                        cidr: Some("10.0.0.0/24".to_string()),
+                        labels: HashMap::new(),
+                    },
+                    rules: vec![mem_rule(90.0, 97.0)],
+                },
+                NodeOverride {
+                    name: "cache".to_string(),
+                    matcher: NodeMatcher {
+                        labels: HashMap::from([("role".to_string(), "cache".to_string())]),
+                        ..Default::default()
+                    },
+                    rules: vec![mem_rule(95.0, 99.0)],
+                },
+            ],
+        )
+        .unwrap();
+        let mut db = node(10, 85, now());
+        db.id = "db-1".to_string();
+        assert!(matches!(dc.check_node(&db).0, HealthStatus::Green));
+        db.system_ip = "10.0.1.1".to_string();
+        assert!(matches!(dc.check_node(&db).0, HealthStatus::Yellow));
+
+        let mut cache = node(10, 92, now());
+        assert!(matches!(dc.check_node(&cache).0, HealthStatus::Red));
+        cache.labels.insert("role".to_string(), "cache".to_string());
+        assert!(matches!(dc.check_node(&cache).0, HealthStatus::Green));
+
+        // API设置的阈值优先级最高 且在副本间共享
+        dc.clone()
+            .set_thresholds("node-1".to_string(), vec![mem_rule(50.0, 60.0)]);
+        assert!(matches!(dc.check_node(&cache).0, HealthStatus::Red));
+        dc.remove_thresholds("node-1");
+        assert!(matches!(dc.check_node(&cache).0, HealthStatus::Green));
+    }
+
+    #[test]
+    fn invalid_override_fails_at_startup() {
+        let group = |name: &str, hostname: &str, cidr: &str| NodeOverride {
+            name: name.to_string(),
+            matcher: NodeMatcher {
+                hostname: Some(hostname.to_string()),
+                cidr: Some(cidr.to_string()),
+                labels: HashMap::new(),
+            },
+            rules: vec![],
+        };
+        let err = Doctor::new(
+            default_rules(),
+            vec![
+                group("a", "db-[", "10.0.0.0/24"),
+                group("b", "db-*", "10.0.0.0/24"),
+                group("c", "web-*", "10.0.0.300/24"),
+            ],
+        )
+        .err()
+        .unwrap();
+        assert!(err.contains("override a: bad hostname glob db-["));
+        assert!(!err.contains("override b"));
+        assert!(err.contains("override c: bad cidr 10.0.0.300/24"));
+    }
+
+    #[test]
+    fn sustained_rule_fires_after_samples_and_recovers_with_hysteresis() {
+        let dc = Doctor::new(
+            vec![Rule {
+                for_samples: Some(3),
+                error_recover: Some(80.0),
+                ..mem_rule(70.0, 90.0)
+            }],
+            vec![],
+        )
+        .unwrap();
+        let mut windows = NodeWindows::new();
+        let t = now();
+        let mut check = |mem, ts| {
+            let n = node(10, mem, ts);
+            dc.check_node_sustained(&n, &mut windows).0
+        };
+        assert!(matches!(check(95, t), HealthStatus::Green));
+        // 同一次上报重复检查不计入次数
+        assert!(matches!(check(95, t), HealthStatus::Green));
+        assert!(matches!(check(95, t + 1), HealthStatus::Green));
+        assert!(matches!(check(95, t + 2), HealthStatus::Red));
+        // 低于触发阈值但未越过恢复阈值 保持Red
+        assert!(matches!(check(85, t + 3), HealthStatus::Red));
+        assert!(matches!(check(75, t + 4), HealthStatus::Yellow));
+        assert!(matches!(check(60, t + 5), HealthStatus::Green));
+    }
+
+    #[test]
+    fn long_sustained_rule_can_fire() {
+        let dc = Doctor::new(
+            vec![Rule {
+                for_samples: Some(100),
+                ..mem_rule(70.0, 90.0)
+            }],
+            vec![],
+        )
+        .unwrap();
+        let mut windows = NodeWindows::new();
+        let t = now();
+        let mut check = |mem, ts| {
+            let n = node(10, mem, ts);
+            dc.check_node_sustained(&n, &mut windows).0
+        };
+        for i in 0..99 {
+            assert!(matches!(check(95, t + i), HealthStatus::Green));
+        }
+        assert!(matches!(check(95, t + 99), HealthStatus::Red));
+        // 时间回拨不影响判断
+        assert!(matches!(check(95, t), HealthStatus::Red));
+    }
+
+    async fn serve() -> String {
+        use axum::{http::HeaderMap, response::Redirect, routing::get};
+        let app = axum::Router::new()
+            .route("/fast", get(|| async { "ok" }))
+            .route("/big", get(|| async { "x".repeat(2 * MAX_BODY_BYTES) }))
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+                    "ok"
+                }),
+            )
+            .route(
+                "/health",
+                get(|headers: HeaderMap| async move {
+                    match headers.get("authorization").and_then(|v| v.to_str().ok()) {
+                        Some("Bearer s3cret") => reqwest::StatusCode::NO_CONTENT,
+                        _ => reqwest::StatusCode::UNAUTHORIZED,
+                    }
+                }),
+            )
+            .route("/moved", get(|| async { Redirect::temporary("/fast") }))
+            .route("/status", get(|| async { r#"{"status":"DOWN"}"# }))
+            .route(
+                "/actuator/health",
+                get(|| async {
+                    (
+                        reqwest::StatusCode::SERVICE_UNAVAILABLE,
+                        r#"{"status":"DOWN","components":{"db":{"status":"UP"},"redis":{"status":"DOWN","details":{"error":"Connection refused"}}}}"#,
+                    )
+                }),
+            );
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        format!("http://{}", addr)
+    }
+
+    fn check(yaml: &str) -> HttpCheck {
+        let srv: crate::config::model::Service = serde_yaml::from_str(yaml).unwrap();
+        HttpCheck::new(&srv).unwrap()
+    }
+
+    #[tokio::test]
+    async fn slow_service_turns_yellow() {
+        let base = serve().await;
+        let dc = Doctor::new(vec![], vec![]).unwrap();
+        let fast = check(&format!(
+            "{{name: api, api: '{}/fast', latency_warn_ms: 200, probe_phases: true}}",
+            base
+        ));
+        let (status, _, timings, _) = dc.check_service(&fast).await;
+        assert!(matches!(status, HealthStatus::Green));
+        assert!(timings.dns.is_some() && timings.connect.is_some());
+        assert!(timings.tls.is_none());
+        let slow = check(&format!(
+            "{{name: api, api: '{}/slow', latency_warn_ms: 200}}",
+            base
+        ));
+        let (status, msg, timings, _) = dc.check_service(&slow).await;
+        assert!(matches!(status, HealthStatus::Yellow));
+        assert!(msg.contains("over 200ms"));
+        assert!(timings.total.unwrap() >= 300);
+        // 未开启probe_phases时只测量请求耗时
+        assert!(timings.dns.is_none() && timings.connect.is_none());
+    }
+
+    #[tokio::test]
+    async fn unresponsive_service_costs_one_timeout() {
+        // 接受连接但从不响应
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut streams = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                streams.push(stream);
+            }
+        });
+        let dc = Doctor::new(vec![], vec![]).unwrap();
+        for scheme in ["http", "https"] {
+            let hang = check(&format!(
+                "{{name: api, api: '{}://{}/', timeout_secs: 1, probe_phases: true}}",
+                scheme, addr
+            ));
+            let start = Instant::now();
+            let (status, msg, timings, _) = dc.check_service(&hang).await;
+            assert!(start.elapsed().as_millis() < 1500, "{}", msg);
+            assert!(matches!(status, HealthStatus::Red));
+            assert!(timings.connect.is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_assertions_are_reported() {
+        let base = serve().await;
+        let dc = Doctor::new(vec![], vec![]).unwrap();
+        let status = check(&format!(
+            "{{name: api, api: '{}/status', assertions: [{{kind: json_path, path: $.status, equals: UP}}]}}",
+            base
+        ));
+        let (status, msg, _, _) = dc.check_service(&status).await;
+        assert!(matches!(status, HealthStatus::Red));
+        assert_eq!(
+            msg,
+            r#"Error: assertion failed: $.status is "DOWN", expected "UP""#
+        );
+    }
+
+    #[tokio::test]
+    async fn oversized_body_is_truncated() {
+        let base = serve().await;
+        let dc = Doctor::new(vec![], vec![]).unwrap();
+        // 没有断言时不读取响应体
+        let big = check(&format!("{{name: api, api: '{}/big'}}", base));
+        assert!(matches!(
+            dc.check_service(&big).await.0,
+            HealthStatus::Green
+        ));
+        // 超过上限的部分截断 不影响其余断言
+        let big = check(&format!(
+            "{{name: api, api: '{}/big', assertions: [{{kind: contains, value: x}}]}}",
+            base
+        ));
+        assert!(matches!(
+            dc.check_service(&big).await.0,
+            HealthStatus::Green
+        ));
+        // 每条size断言各自判断 取最严重的级别
+        let big = check(&format!(
+            "{{name: api, api: '{}/big', assertions: [{{kind: size, max: 100, severity: yellow}}, {{kind: size, max: 200}}]}}",
+            base
+        ));
+        let (status, msg, _, _) = dc.check_service(&big).await;
+        assert!(matches!(status, HealthStatus::Red));
+        assert_eq!(
+            msg,
+            "Warn: assertion failed: body size over 100 bytes\nError: assertion failed: body size over 200 bytes"
+        );
+        // 恰好等于max时通过
+        let fast = check(&format!(
+            "{{name: api, api: '{}/fast', assertions: [{{kind: size, max: 2}}]}}",
+            base
+        ));
+        assert!(matches!(
+            dc.check_service(&fast).await.0,
+            HealthStatus::Green
+        ));
+    }
+
+    #[tokio::test]
+    async fn actuator_reports_failed_components() {
+        let base = serve().await;
+        let dc = Doctor::new(vec![], vec![]).unwrap();
+        let actuator = check(&format!(
+            "{{name: api, api: '{}/actuator/health', kind: actuator}}",
+            base
+        ));
+        let (status, msg, _, components) = dc.check_service(&actuator).await;
+        assert!(matches!(status, HealthStatus::Red));
+        assert_eq!(msg, "Error: redis is DOWN: Connection refused");
+        assert_eq!(components.len(), 2);
+        // 普通http检查只看状态码
+        let http = check(&format!("{{name: api, api: '{}/actuator/health'}}", base));
+        let (status, _, _, components) = dc.check_service(&http).await;
+        assert!(matches!(status, HealthStatus::Yellow));
+        assert!(components.is_empty());
+    }
+
+    #[tokio::test]
+    async fn http_check_options() {
+        let base = serve().await;
+        let dc = Doctor::new(vec![], vec![]).unwrap();
+        let status = |yaml: String| {
+            let check = check(&yaml);
+            let dc = dc.clone();
+            async move { dc.check_service(&check).await.0 }
+        };
+        // 需要token 且返回204
+        let health = format!("{{name: api, api: '{}/health', expect_status: [2xx]", base);
+        assert!(matches!(
+            status(format!("{}}}", health)).await,
+            HealthStatus::Yellow
+        ));
+        assert!(matches!(
+            status(format!(
+                "{}, auth: {{kind: bearer, token: s3cret}}}}",
+                health
+            ))
+            .await,
+            HealthStatus::Green
+        ));
+        assert!(matches!(
+            status(format!(
+                "{}, headers: {{Authorization: Bearer s3cret}}}}",
+                health
+            ))
+            .await,
+            HealthStatus::Green
+        ));
+        // 默认跟随重定向
+        let moved = format!("{{name: api, api: '{}/moved'", base);
+        assert!(matches!(
+            status(format!("{}}}", moved)).await,
+            HealthStatus::Green
+        ));
+        assert!(matches!(
+            status(format!("{}, max_redirects: 0}}", moved)).await,
+            HealthStatus::Yellow
+        ));
+        assert!(matches!(
+            status(format!(
+                "{}, max_redirects: 0, expect_status: [3xx]}}",
+                moved
+            ))
+            .await,
+            HealthStatus::Green
+        ));
+    }
+
+    #[tokio::test]
+    async fn tcp_check_matches_banner() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        // 先发送banner 收到PING后回复PONG
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    stream.write_all(b"SSH-2.0-OpenSSH_9.3\r\n").await.unwrap();
+                    let mut buf = [0u8; 64];
+                    if let Ok(n) = stream.read(&mut buf).await {
+                        if buf[..n].starts_with(b"PING") {
+                            let _ = stream.write_all(b"+PONG\r\n").await;
+                        }
+                    }
+                });
+            }
+        });
+        let dc = Doctor::new(vec![], vec![]).unwrap();
+        let tcp = |extra: &str| {
+            let srv: crate::config::model::Service = serde_yaml::from_str(&format!(
+                "{{name: ssh, api: '{}', kind: tcp, timeout_secs: 1{}}}",
+                addr, extra
+            ))
+            .unwrap();
+            TcpCheck::new(&srv).unwrap()
+        };
+        let (status, _, timings) = dc.check_tcp(&tcp("")).await;
+        assert!(matches!(status, HealthStatus::Green));
+        assert!(timings.dns.is_some() && timings.connect.is_some());
+        let (status, _, _) = dc.check_tcp(&tcp(", banner: SSH-2.0")).await;
+        assert!(matches!(status, HealthStatus::Green));
+        let (status, _, _) = dc
+            .check_tcp(&tcp(r#", payload: "PING\r\n", banner_regex: '\+PONG'"#))
+            .await;
+        assert!(matches!(status, HealthStatus::Green));
+        // 等待banner直到截止时间 整个检查不超过一次timeout
+        let start = Instant::now();
+        let (status, msg, _) = dc.check_tcp(&tcp(", banner_regex: '^220 '")).await;
+        assert!(start.elapsed().as_millis() < 1500);
+        assert!(matches!(status, HealthStatus::Red));
+        assert!(msg.contains("banner not matched"));
+        let (status, msg, _) = dc.check_tcp(&tcp(", latency_warn_ms: 0")).await;
+        assert!(matches!(status, HealthStatus::Yellow));
+        assert!(msg.starts_with("Warn: connected in"), "{}", msg);
+
+        // 端口未监听
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = closed.local_addr().unwrap().port();
+        drop(closed);
+        let srv: crate::config::model::Service = serde_yaml::from_str(&format!(
+            "{{name: db, api: '127.0.0.1:{}', kind: tcp}}",
+            port
+        ))
+        .unwrap();
+        let (status, msg, timings) = dc.check_tcp(&TcpCheck::new(&srv).unwrap()).await;
+        assert!(matches!(status, HealthStatus::Red));
+        assert!(msg.starts_with("Error: connect"));
+        assert!(timings.connect.is_none());
+    }
+}
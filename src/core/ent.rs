@@ -1,4 +1,6 @@
 use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Debug, Serialize, Clone)]
 pub struct Node {
@@ -21,17 +23,44 @@ pub struct Node {
     pub disk_status: String,
     pub last_updated: u64,
     pub status_msg: Option<String>,
+    pub labels: HashMap<String, String>,
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct Service {
     pub name: String,
     pub api: String,
+    // 最近一次检查的总耗时(毫秒)
     pub latency: u128,
+    pub timings: Timings,
+    // 结构化健康接口中各依赖组件的状态
+    pub components: Vec<Component>,
     pub last_updated: u64,
+    pub status_msg: Option<String>,
+    pub labels: HashMap<String, String>,
 }
 
+// 依赖组件的检查结果 name为组件路径 如 db 或 db/primary
 #[derive(Debug, Clone, Serialize)]
+pub struct Component {
+    pub name: String,
+    // 接口返回的原始状态 如 UP/DOWN/OUT_OF_SERVICE 或 pass/warn/fail
+    pub status: String,
+    pub health: HealthStatus,
+    pub detail: Option<String>,
+}
+
+// 服务检查的耗时(毫秒) 未能测量的阶段为None
+// dns/connect/tls来自开启probe_phases时的预探测连接 total为请求本身的耗时
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Timings {
+    pub dns: Option<u128>,
+    pub connect: Option<u128>,
+    pub tls: Option<u128>,
+    pub total: Option<u128>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
 pub enum HealthStatus {
     Red,
     Yellow,
@@ -42,17 +71,93 @@ pub enum HealthStatus {
 pub struct HealthInfo {
     pub target: Target,
     pub status: HealthStatus,
+    // 由Logger根据告警状态填充
+    pub alert: Option<Alert>,
+}
+
+impl HealthInfo {
+    pub fn msg(&self) -> &str {
+        let msg = match &self.target {
+            Target::Node(_, node) => node.as_ref().and_then(|n| n.status_msg.as_deref()),
+            Target::Service(_, srv) => srv.as_ref().and_then(|s| s.status_msg.as_deref()),
+        };
+        msg.unwrap_or_default()
+    }
+    // 外部告警平台用来合并同一告警的键 重启后保持不变
+    // 规则取自告警触发时命中的规则 告警持续期间其他规则越限不会改变该键
+    pub fn dedup_key(&self) -> Option<String> {
+        self.alert
+            .as_ref()
+            .map(|a| format!("{}/{}", self.target.key(), a.rule))
+    }
+}
+
+// 未被跟踪的目标即为inactive
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+pub enum AlertState {
+    Pending,
+    Firing,
+    // 状态频繁变化 只通知一次 稳定后转为firing或resolved
+    Flapping,
+    Resolved,
+}
+
+// 单个目标的告警状态
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub id: String,
+    // 触发告警的规则 节点为命中的指标 服务为health 触发后直到恢复保持不变
+    pub rule: String,
+    pub state: AlertState,
+    // 进入当前状态的时间
+    pub since: u64,
+    pub last_notified: Option<u64>,
+    // 一键确认链接 需配置server.public_url
+    pub ack_url: Option<String>,
+    pub acked_by: Option<String>,
+    pub acked_at: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize)]
+#[allow(clippy::large_enum_variant)]
 pub enum Target {
     Node(String, Option<Node>),
     Service(String, Option<Service>),
 }
 
+impl Target {
+    // 告警等按目标区分状态时使用的唯一键
+    pub fn key(&self) -> String {
+        match self {
+            Target::Node(id, _) => format!("node/{}", id),
+            Target::Service(name, _) => format!("service/{}", name),
+        }
+    }
+    pub fn name(&self) -> &str {
+        match self {
+            Target::Node(id, _) => id,
+            Target::Service(name, _) => name,
+        }
+    }
+    pub fn labels(&self) -> Option<&HashMap<String, String>> {
+        match self {
+            Target::Node(_, node) => node.as_ref().map(|n| &n.labels),
+            Target::Service(_, srv) => srv.as_ref().map(|s| &s.labels),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Event {
     Heartbeat(HealthInfo),
     Offline(Target),
     CheckAll,
 }
+
+// 当前的unix时间戳(秒)
+pub fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
@@ -1,38 +1,79 @@
-use crate::core::alarm::*;
+use crate::core::alert::Alerts;
+use crate::core::dispatch::Dispatcher;
 use crate::core::doctor::*;
 use crate::core::ent::*;
+use crate::core::maintenance::MaintenanceWindows;
+use crate::core::silence::Silences;
+use crate::core::window::NodeWindows;
 use std::collections::HashMap;
+
+// 服务检查只有一条规则 节点未命中具体指标时(如pending)使用node
+const SERVICE_RULE: &str = "health";
+const NODE_RULE: &str = "node";
 // 记录数据同时判断是否需要报警
 pub struct Logger {
-    nodes: HashMap<String, Node>,       //存储原始的节点信息
-    services: HashMap<String, Service>, //存储原始的服务信息
+    nodes: HashMap<String, Node>,          //存储原始的节点信息
+    services: HashMap<String, Service>,    //存储原始的服务信息
+    windows: HashMap<String, NodeWindows>, //节点各规则的短期采样窗口
+    alerts: Alerts,                        //各目标的告警状态 与API共享
     dc: Doctor,
-    alarm: Alarm,
+    dispatcher: Dispatcher,
+    silences: Silences,              //静默规则 命中的告警只记录不通知
+    maintenance: MaintenanceWindows, //维护窗口 窗口内同样只记录不通知
+    clock: fn() -> u64,              //当前时间 测试中可替换
 }
 
 impl Logger {
-    pub fn new(dc: Doctor, alarm: Alarm) -> Logger {
+    pub fn new(
+        dc: Doctor,
+        dispatcher: Dispatcher,
+        alerts: Alerts,
+        silences: Silences,
+        maintenance: MaintenanceWindows,
+    ) -> Logger {
         Logger {
             nodes: HashMap::new(),
             services: HashMap::new(),
+            windows: HashMap::new(),
+            alerts,
             dc,
-            alarm,
+            dispatcher,
+            silences,
+            maintenance,
+            clock: timestamp,
         }
     }
     pub fn log(&mut self, event: Event) {
         //TODO: 带颜色的打印控制台日志
         //1. 打印日志 记录节点状态
         //2. 将节点状况计入Map中
-        //3. 根据不同情况 决定是否立即邮件抱紧
+        //3. 根据告警状态的变化 决定是否立即邮件报警
         match event {
-            Event::Heartbeat(health) => {
+            Event::Heartbeat(mut health) => {
+                // 节点需结合采样窗口重新判断 单次超限不直接报警
+                let mut pending = false;
+                let mut rule = SERVICE_RULE.to_string();
+                if let Target::Node(id, Some(node)) = &mut health.target {
+                    let (status, msg, r, p) = self.diagnose_node(id, node);
+                    node.status_msg = Some(msg);
+                    health.status = status;
+                    rule = r;
+                    pending = p;
+                }
                 match health.status {
                     HealthStatus::Green => tracing::info!("recv heartbeat: need nothing"),
                     HealthStatus::Yellow => tracing::info!("recv heartbeat: need warning"),
-                    HealthStatus::Red => {
-                        tracing::info!("recv heartbeat: it's error, notify now");
-                        self.alarm.notify(vec![health.clone()]);
-                    }
+                    HealthStatus::Red => tracing::info!("recv heartbeat: it's error"),
+                }
+                let event = self.alerts.write().unwrap().update(
+                    health.clone(),
+                    &rule,
+                    pending,
+                    (self.clock)(),
+                );
+                if let Some(event) = event {
+                    tracing::info!("alert state changed, notify now");
+                    self.notify(vec![event]);
                 }
                 match health.target {
                     Target::Node(id, node) => self.update_node(id, node),
@@ -42,6 +83,54 @@ impl Logger {
             Event::Offline(target) => self.offline(target),
             Event::CheckAll => self.tranverse_check(),
         };
+        let now = (self.clock)();
+        let mut alerts = self.alerts.write().unwrap();
+        alerts.expire_acks(now);
+        let (silences, maintenance) = (&self.silences, &self.maintenance);
+        let quiet = |h: &HealthInfo| quiet(silences, maintenance, h, now);
+        self.dispatcher.repeat(alerts.firing(), quiet, now);
+    }
+    // 定时调用 推进未确认告警的升级 发送等待已到期的告警分组
+    pub fn tick(&mut self) {
+        let now = (self.clock)();
+        let mut alerts = self.alerts.write().unwrap();
+        alerts.expire_acks(now);
+        let (silences, maintenance) = (&self.silences, &self.maintenance);
+        let quiet = |h: &HealthInfo| quiet(silences, maintenance, h, now);
+        self.dispatcher.escalate(alerts.firing(), quiet, now);
+        self.dispatcher.flush(now);
+    }
+    // 同一批事件按路由分发给通知渠道
+    // 恢复事件不受静默与维护窗口影响 dispatcher只发给通知过的路由 外部平台的事件才能关闭
+    fn notify(&mut self, mut events: Vec<HealthInfo>) {
+        let now = (self.clock)();
+        events.retain(|e| {
+            let resolved = e
+                .alert
+                .as_ref()
+                .is_some_and(|a| a.state == AlertState::Resolved);
+            let muted = !resolved && muted(&self.silences, &self.maintenance, e, now);
+            if muted {
+                tracing::info!("alert of {} is muted", e.target.key());
+            }
+            !muted
+        });
+        self.dispatcher.dispatch(events, now);
+    }
+    // 返回节点持续状态、消息、命中的规则以及是否处于pending(本次超限但未满足持续条件)
+    fn diagnose_node(&mut self, id: &str, node: &Node) -> (HealthStatus, String, String, bool) {
+        let windows = self.windows.entry(id.to_string()).or_default();
+        let (status, msg, metrics) = self.dc.check_node_sustained(node, windows);
+        let pending =
+            status == HealthStatus::Green && self.dc.check_node(node).0 != HealthStatus::Green;
+        let mut names: Vec<&str> = metrics.iter().map(|m| m.name()).collect();
+        names.sort();
+        let rule = if names.is_empty() {
+            NODE_RULE.to_string()
+        } else {
+            names.join("+")
+        };
+        (status, msg, rule, pending)
     }
     fn update_node(&mut self, id: String, node: Option<Node>) {
         tracing::info!("try to update node {:?},{:?}", id, node);
@@ -62,8 +151,10 @@ impl Logger {
         };
     }
     fn offline(&mut self, target: Target) {
+        self.alerts.write().unwrap().remove(&target, (self.clock)());
         match target {
             Target::Node(id, _) => {
+                self.windows.remove(&id);
                 let node = self.nodes.remove(&id).unwrap();
                 tracing::info!("node offline {:?}", node);
             }
@@ -73,22 +164,270 @@ impl Logger {
             }
         };
     }
-    fn tranverse_check(&self) {
+    fn tranverse_check(&mut self) {
         let mut result: Vec<HealthInfo> = Vec::new();
-        //TODO: 检查节点 遍历Map 检查每个节点的健康状态
-        for (id, node) in self.nodes.iter() {
-            let (status, msg) = self.dc.check_node(node);
-            let mut n = node.clone();
-            n.status_msg = Some(msg);
-            result.push(HealthInfo {
-                target: Target::Node(String::from(id), Some(n)),
+        let mut changed: Vec<HealthInfo> = Vec::new();
+        //检查节点 遍历Map 检查每个节点的健康状态
+        let nodes: Vec<Node> = self.nodes.values().cloned().collect();
+        for mut node in nodes {
+            let id = node.id.clone();
+            let (status, msg, rule, pending) = self.diagnose_node(&id, &node);
+            node.status_msg = Some(msg);
+            let health = HealthInfo {
+                target: Target::Node(id, Some(node)),
                 status,
-            });
+                alert: None,
+            };
+            let event =
+                self.alerts
+                    .write()
+                    .unwrap()
+                    .update(health.clone(), &rule, pending, (self.clock)());
+            if let Some(event) = event {
+                changed.push(event);
+            }
+            result.push(health);
         }
         //TODO: 检查服务
         //1. 将状态先输出至单独的本地文件 用以留档
         tracing::info!("finished check all nodes\n{:?}", result);
-        //2. 发邮件通知当前的文件健康状态
-        self.alarm.notify(result);
+        //2. 仅对告警状态有变化的节点发邮件通知
+        if !changed.is_empty() {
+            self.notify(changed);
+        }
+    }
+}
+
+// 处于静默或维护窗口中的告警不发送触发通知
+fn muted(
+    silences: &Silences,
+    maintenance: &MaintenanceWindows,
+    info: &HealthInfo,
+    now: u64,
+) -> bool {
+    silences.read().unwrap().silenced(info, now) || maintenance.active(info, now).is_some()
+}
+
+// 已确认(直到确认过期)或抖动中的告警不再重复通知与升级
+fn quiet(
+    silences: &Silences,
+    maintenance: &MaintenanceWindows,
+    info: &HealthInfo,
+    now: u64,
+) -> bool {
+    info.alert
+        .as_ref()
+        .is_some_and(|a| a.acked_at.is_some() || a.state == AlertState::Flapping)
+        || muted(silences, maintenance, info, now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::model::{Flapping, Maintenance, OnCall, Route};
+    use crate::core::ack::AckSigner;
+    use crate::core::alert::AlertBook;
+    use crate::core::notifier::Notifier;
+    use crate::core::silence::{NewSilence, SilenceStore};
+    use std::{
+        fs,
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex, RwLock,
+        },
+    };
+    use uuid::Uuid;
+
+    type Sent = Arc<Mutex<Vec<(AlertState, String)>>>;
+
+    // 替换Logger时钟用的当前时间
+    static NOW: AtomicU64 = AtomicU64::new(0);
+
+    struct Recorder(&'static str, Sent);
+
+    impl Notifier for Recorder {
+        fn name(&self) -> &str {
+            self.0
+        }
+        fn notify(&self, events: Vec<HealthInfo>) {
+            let mut sent = self.1.lock().unwrap();
+            for e in events {
+                sent.push((e.alert.unwrap().state, e.target.key()));
+            }
+        }
+    }
+
+    fn logger(sent: &Sent, maintenance: MaintenanceWindows) -> (Logger, Silences, PathBuf) {
+        let path = std::env::temp_dir().join(format!("silences-{}.json", Uuid::new_v4()));
+        let silences = Arc::new(RwLock::new(SilenceStore::open(&path).unwrap()));
+        let dispatcher = Dispatcher::new(
+            vec![Box::new(Recorder("pager", sent.clone()))],
+            None,
+            OnCall::default(),
+            3600,
+        )
+        .unwrap();
+        let logger = Logger::new(
+            Doctor::new(vec![], vec![]).unwrap(),
+            dispatcher,
+            Arc::new(RwLock::new(AlertBook::default())),
+            silences.clone(),
+            maintenance,
+        );
+        (logger, silences, path)
+    }
+
+    fn heartbeat(name: &str, status: HealthStatus) -> Event {
+        Event::Heartbeat(HealthInfo {
+            target: Target::Service(name.to_string(), None),
+            status,
+            alert: None,
+        })
+    }
+
+    fn take(sent: &Sent) -> Vec<(AlertState, String)> {
+        std::mem::take(&mut *sent.lock().unwrap())
+    }
+
+    #[test]
+    fn resolve_is_sent_while_silenced() {
+        let sent: Sent = Arc::default();
+        let (mut logger, silences, path) = logger(&sent, MaintenanceWindows::default());
+        logger.log(heartbeat("api", HealthStatus::Red));
+        assert_eq!(
+            take(&sent),
+            vec![(AlertState::Firing, "service/api".to_string())]
+        );
+        let new: NewSilence = serde_json::from_value(serde_json::json!({
+            "matchers": {"kind": "service"},
+            "ends_at": timestamp() + 3600,
+            "created_by": "ops",
+        }))
+        .unwrap();
+        let (_, snapshot) = silences.write().unwrap().add(new, timestamp()).unwrap();
+        snapshot.write().unwrap();
+        logger.log(heartbeat("api", HealthStatus::Red));
+        logger.log(heartbeat("api", HealthStatus::Green));
+        assert_eq!(
+            take(&sent),
+            vec![(AlertState::Resolved, "service/api".to_string())]
+        );
+
+        // 静默期间触发又恢复的告警从未通知过 也不发送恢复
+        logger.log(heartbeat("web", HealthStatus::Red));
+        logger.log(heartbeat("web", HealthStatus::Green));
+        assert!(take(&sent).is_empty());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn resolve_is_sent_inside_maintenance_window() {
+        let sent: Sent = Arc::default();
+        let (mut logger, _, path) = logger(&sent, MaintenanceWindows::default());
+        logger.log(heartbeat("api", HealthStatus::Red));
+        assert_eq!(take(&sent).len(), 1);
+        // 告警触发之后进入维护窗口 窗口每分钟开始 持续一小时 即一直处于窗口中
+        let config: Vec<Maintenance> = serde_yaml::from_str(
+            r#"
+- name: backup
+  match: {kind: service}
+  cron: "* * * * *"
+  duration_secs: 3600
+"#,
+        )
+        .unwrap();
+        logger.maintenance = MaintenanceWindows::new(config).unwrap();
+        logger.log(heartbeat("web", HealthStatus::Red));
+        assert!(take(&sent).is_empty());
+        logger.log(heartbeat("api", HealthStatus::Green));
+        logger.log(heartbeat("web", HealthStatus::Green));
+        assert_eq!(
+            take(&sent),
+            vec![(AlertState::Resolved, "service/api".to_string())]
+        );
+        // 未添加静默规则时不会生成文件
+        if path.exists() {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn flapping_alert_is_routed_by_severity_and_not_repeated() {
+        let (chat, pager, lead): (Sent, Sent, Sent) = Default::default();
+        let route: Route = serde_yaml::from_str(
+            r#"
+receiver: chat
+routes:
+  - match: {severity: Red}
+    receiver: pager
+    repeat_interval: 60
+    continue: true
+  - match: {severity: Red}
+    escalation: red
+"#,
+        )
+        .unwrap();
+        let oncall: OnCall = serde_yaml::from_str(
+            r#"
+escalations:
+  - name: red
+    steps:
+      - {receiver: pager}
+      - {receiver: lead, delay_secs: 120}
+"#,
+        )
+        .unwrap();
+        let dispatcher = Dispatcher::new(
+            vec![
+                Box::new(Recorder("chat", chat.clone())),
+                Box::new(Recorder("pager", pager.clone())),
+                Box::new(Recorder("lead", lead.clone())),
+            ],
+            Some(route),
+            oncall,
+            0,
+        )
+        .unwrap();
+        let flapping = Flapping {
+            window_secs: 100,
+            threshold: 4,
+            recover_threshold: 2,
+        };
+        let book = AlertBook::new(AckSigner::default(), 0, Some(flapping));
+        let path = std::env::temp_dir().join(format!("silences-{}.json", Uuid::new_v4()));
+        let mut logger = Logger::new(
+            Doctor::new(vec![], vec![]).unwrap(),
+            dispatcher,
+            Arc::new(RwLock::new(book)),
+            Arc::new(RwLock::new(SilenceStore::open(&path).unwrap())),
+            MaintenanceWindows::default(),
+        );
+        logger.clock = || NOW.load(Ordering::SeqCst);
+        // 每次采样后与主循环一样定时检查升级
+        let mut sample = |status, now| {
+            NOW.store(now, Ordering::SeqCst);
+            logger.log(heartbeat("api", status));
+            logger.tick();
+        };
+        use HealthStatus::*;
+        for (i, status) in [Green, Red, Green, Red].into_iter().enumerate() {
+            sample(status, i as u64 * 10);
+        }
+        for sent in [&chat, &pager, &lead] {
+            take(sent);
+        }
+        // 第4次变化落在恢复的采样上 仍按Red路由
+        sample(Green, 40);
+        let flapping = vec![(AlertState::Flapping, "service/api".to_string())];
+        assert!(take(&chat).is_empty());
+        assert_eq!(take(&pager), [flapping.clone(), flapping].concat());
+        // 抖动期间超过重复间隔与升级延迟 均不通知
+        for (i, status) in [Red, Green, Red, Green, Red].into_iter().enumerate() {
+            sample(status, 50 + i as u64 * 40);
+        }
+        assert!(take(&chat).is_empty());
+        assert!(take(&pager).is_empty());
+        assert!(take(&lead).is_empty());
     }
 }
@@ -0,0 +1,208 @@
+use crate::config::model::Maintenance;
+use crate::core::dispatch::Matcher;
+use crate::core::ent::HealthInfo;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveTime, TimeZone, Weekday};
+use cron::Schedule;
+use std::str::FromStr;
+
+enum Period {
+    // 窗口开始时间与持续时长
+    Cron(Box<Schedule>, Duration),
+    Weekly {
+        days: Vec<Weekday>,
+        start: NaiveTime,
+        end: NaiveTime,
+    },
+}
+
+impl Period {
+    fn contains(&self, t: DateTime<Local>) -> bool {
+        match self {
+            // 最近一次开始时间在(t - duration, t]内
+            Period::Cron(schedule, duration) => schedule
+                .after(&(t - *duration))
+                .next()
+                .is_some_and(|start| start <= t),
+            Period::Weekly { days, start, end } => {
+                let time = t.time();
+                let today = days.contains(&t.weekday());
+                if start <= end {
+                    today && *start <= time && time < *end
+                } else {
+                    // 跨越午夜 前一天开始的窗口延续到今天
+                    let yesterday = days.contains(&t.weekday().pred());
+                    (today && time >= *start) || (yesterday && time < *end)
+                }
+            }
+        }
+    }
+}
+
+struct Window {
+    name: String,
+    matcher: Matcher,
+    period: Period,
+}
+
+// 配置文件中的维护窗口 启动时编译 配置有误时返回全部错误
+#[derive(Default)]
+pub struct MaintenanceWindows {
+    windows: Vec<Window>,
+}
+
+impl MaintenanceWindows {
+    pub fn new(configs: Vec<Maintenance>) -> Result<MaintenanceWindows, String> {
+        let mut windows = Vec::new();
+        let mut errors = Vec::new();
+        for config in configs {
+            let name = config.name.clone();
+            match Window::new(config) {
+                Ok(window) => windows.push(window),
+                Err(e) => errors.push(format!("maintenance {}: {}", name, e)),
+            }
+        }
+        if errors.is_empty() {
+            Ok(MaintenanceWindows { windows })
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+    // 返回目标当前所处的维护窗口名
+    pub fn active(&self, info: &HealthInfo, now: u64) -> Option<&str> {
+        let t = Local.timestamp_opt(now as i64, 0).single()?;
+        self.windows
+            .iter()
+            .find(|w| w.period.contains(t) && w.matcher.matches(info))
+            .map(|w| w.name.as_str())
+    }
+}
+
+impl Window {
+    fn new(config: Maintenance) -> Result<Window, String> {
+        let period = match (config.cron, config.weekly) {
+            (Some(cron), None) => {
+                // 兼容不带秒的5段式写法
+                let expr = if cron.split_whitespace().count() == 5 {
+                    format!("0 {}", cron)
+                } else {
+                    cron.clone()
+                };
+                let schedule =
+                    Schedule::from_str(&expr).map_err(|e| format!("bad cron {}: {}", cron, e))?;
+                let secs = config
+                    .duration_secs
+                    .ok_or("cron window needs duration_secs")?;
+                Period::Cron(Box::new(schedule), Duration::seconds(secs as i64))
+            }
+            (None, Some(weekly)) => {
+                let days = weekly
+                    .days
+                    .iter()
+                    .map(|d| d.parse().map_err(|_| format!("bad weekday {}", d)))
+                    .collect::<Result<_, _>>()?;
+                let time = |s: &str| {
+                    NaiveTime::parse_from_str(s, "%H:%M")
+                        .map_err(|e| format!("bad time {}: {}", s, e))
+                };
+                Period::Weekly {
+                    days,
+                    start: time(&weekly.start)?,
+                    end: time(&weekly.end)?,
+                }
+            }
+            _ => return Err("exactly one of cron and weekly is required".to_string()),
+        };
+        Ok(Window {
+            name: config.name,
+            matcher: Matcher::new(config.matcher)?,
+            period,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ent::*;
+
+    fn node(id: &str) -> HealthInfo {
+        HealthInfo {
+            target: Target::Node(id.to_string(), None),
+            status: HealthStatus::Red,
+            alert: None,
+        }
+    }
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32) -> u64 {
+        Local
+            .with_ymd_and_hms(y, m, d, h, min, 0)
+            .unwrap()
+            .timestamp() as u64
+    }
+
+    #[test]
+    fn weekly_and_cron_windows() {
+        let configs: Vec<Maintenance> = serde_yaml::from_str(
+            r#"
+- name: backup
+  match: {name: "db-*"}
+  weekly: {days: [Sun], start: "02:00", end: "04:00"}
+- name: nightly
+  match: {name: "web-*"}
+  weekly: {days: [Fri], start: "23:00", end: "01:00"}
+- name: patch
+  match: {name: "cache-*"}
+  cron: "30 3 1 * *"
+  duration_secs: 1800
+"#,
+        )
+        .unwrap();
+        let windows = MaintenanceWindows::new(configs).unwrap();
+        // 2023-01-01是周日
+        assert_eq!(
+            windows.active(&node("db-1"), at(2023, 1, 1, 3, 0)),
+            Some("backup")
+        );
+        assert_eq!(windows.active(&node("db-1"), at(2023, 1, 1, 4, 0)), None);
+        assert_eq!(windows.active(&node("web-1"), at(2023, 1, 1, 3, 0)), None);
+        // 周五23点开始 跨到周六凌晨
+        assert!(windows
+            .active(&node("web-1"), at(2023, 1, 6, 23, 30))
+            .is_some());
+        assert!(windows
+            .active(&node("web-1"), at(2023, 1, 7, 0, 30))
+            .is_some());
+        assert!(windows
+            .active(&node("web-1"), at(2023, 1, 7, 1, 30))
+            .is_none());
+        // 每月1日03:30起30分钟
+        assert!(windows
+            .active(&node("cache-1"), at(2023, 1, 1, 3, 45))
+            .is_some());
+        assert!(windows
+            .active(&node("cache-1"), at(2023, 1, 1, 4, 0))
+            .is_none());
+        assert!(windows
+            .active(&node("cache-1"), at(2023, 1, 2, 3, 45))
+            .is_none());
+    }
+
+    #[test]
+    fn invalid_windows_fail_at_startup() {
+        let configs: Vec<Maintenance> = serde_yaml::from_str(
+            r#"
+- name: a
+  cron: "not a cron"
+  duration_secs: 60
+- name: b
+  weekly: {days: [Someday], start: "02:00", end: "04:00"}
+- name: c
+"#,
+        )
+        .unwrap();
+        let err = MaintenanceWindows::new(configs).err().unwrap();
+        assert!(err.contains("maintenance a: bad cron"));
+        assert!(err.contains("maintenance b: bad weekday Someday"));
+        assert!(err.contains("maintenance c: exactly one"));
+    }
+}
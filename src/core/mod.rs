@@ -1,12 +1,35 @@
+pub mod ack;
 pub mod alarm;
+pub mod alert;
 pub mod api;
+pub mod assertion;
+pub mod chat;
+pub mod check;
 pub mod collector;
+pub mod dispatch;
 pub mod doctor;
 pub mod ent;
 pub mod logger;
-pub use alarm::*;
+pub mod maintenance;
+pub mod notifier;
+pub mod oncall;
+pub mod outbox;
+pub mod pager;
+pub mod payload;
+pub mod probe;
+pub mod render;
+pub mod robot;
+pub mod silence;
+pub mod webhook;
+pub mod window;
+pub use ack::AckSigner;
+pub use alert::AlertBook;
 pub use api::*;
 pub use collector::ServiceChecker;
+pub use dispatch::Dispatcher;
 pub use doctor::*;
 pub use ent::*;
 pub use logger::*;
+pub use maintenance::MaintenanceWindows;
+pub use notifier::*;
+pub use silence::SilenceStore;
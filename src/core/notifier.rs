@@ -0,0 +1,77 @@
+use crate::config::model::{Channel, ChatHook, NotifierConfig, Robot, Smtp};
+use crate::core::alarm::Alarm;
+use crate::core::chat::{ChatKind, ChatNotifier};
+use crate::core::ent::HealthInfo;
+use crate::core::pager::PagerNotifier;
+use crate::core::robot::{RobotKind, RobotNotifier};
+use crate::core::webhook::WebhookNotifier;
+
+// 告警通知渠道 Logger会把同一批事件分发给所有渠道
+pub trait Notifier: Send + Sync {
+    fn name(&self) -> &str;
+    fn notify(&self, events: Vec<HealthInfo>);
+}
+
+fn robot_notifier(
+    name: String,
+    kind: RobotKind,
+    robot: Robot,
+) -> Result<Box<dyn Notifier>, String> {
+    RobotNotifier::new(name, kind, robot).map(|r| Box::new(r) as _)
+}
+
+fn chat_notifier(
+    name: String,
+    kind: ChatKind,
+    hook: ChatHook,
+    public_url: &Option<String>,
+) -> Result<Box<dyn Notifier>, String> {
+    ChatNotifier::new(name, kind, hook, public_url.clone()).map(|c| Box::new(c) as _)
+}
+
+// 根据配置创建所有通知渠道 顶层的smtp配置注册为名为smtp的渠道
+// 任一渠道配置有误都会返回全部错误信息 public_url用于在消息中附带链接
+pub fn build_notifiers(
+    smtp: Option<Smtp>,
+    configs: Vec<NotifierConfig>,
+    public_url: Option<String>,
+) -> Result<Vec<Box<dyn Notifier>>, String> {
+    let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+    let mut errors = Vec::new();
+    let smtp = smtp.map(|smtp| NotifierConfig {
+        name: "smtp".to_string(),
+        channel: Channel::Smtp(smtp),
+    });
+    for config in smtp.into_iter().chain(configs) {
+        let notifier: Result<Box<dyn Notifier>, String> = match config.channel {
+            Channel::Smtp(smtp) => Alarm::new(config.name, smtp).map(|a| Box::new(a) as _),
+            Channel::Webhook(hook) => {
+                WebhookNotifier::new(config.name, hook).map(|w| Box::new(w) as _)
+            }
+            Channel::Dingtalk(robot) => robot_notifier(config.name, RobotKind::DingTalk, robot),
+            Channel::Feishu(robot) => robot_notifier(config.name, RobotKind::Feishu, robot),
+            Channel::Wecom(robot) => robot_notifier(config.name, RobotKind::WeCom, robot),
+            Channel::Slack(hook) => chat_notifier(config.name, ChatKind::Slack, hook, &public_url),
+            Channel::Teams(hook) => chat_notifier(config.name, ChatKind::Teams, hook, &public_url),
+            Channel::Discord(hook) => {
+                chat_notifier(config.name, ChatKind::Discord, hook, &public_url)
+            }
+            Channel::Pagerduty(pd) => PagerNotifier::pagerduty(config.name, pd, public_url.clone())
+                .map(|p| Box::new(p) as _),
+            Channel::Opsgenie(og) => PagerNotifier::opsgenie(config.name, og, public_url.clone())
+                .map(|p| Box::new(p) as _),
+        };
+        match notifier {
+            Ok(notifier) => {
+                tracing::info!("notifier {} registered", notifier.name());
+                notifiers.push(notifier);
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+    if errors.is_empty() {
+        Ok(notifiers)
+    } else {
+        Err(errors.join("\n"))
+    }
+}
@@ -0,0 +1,281 @@
+use crate::config::model::{Escalation, OnCall, Schedule};
+use chrono::{Local, NaiveDateTime, TimeZone};
+
+fn parse_time(s: &str) -> Result<NaiveDateTime, String> {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").map_err(|e| format!("bad time {}: {}", s, e))
+}
+
+// 按本地时间计算轮值 交接时刻不受夏令时影响
+struct Rotation {
+    name: String,
+    receivers: Vec<usize>,
+    start: NaiveDateTime,
+    handoff_days: i64,
+    overrides: Vec<(NaiveDateTime, NaiveDateTime, usize)>,
+}
+
+impl Rotation {
+    fn new(
+        config: Schedule,
+        receiver: &impl Fn(&str) -> Result<usize, String>,
+    ) -> Result<Rotation, String> {
+        if config.receivers.is_empty() {
+            return Err("receivers is empty".to_string());
+        }
+        if config.handoff_days == 0 {
+            return Err("handoff_days must be positive".to_string());
+        }
+        let overrides = config
+            .overrides
+            .iter()
+            .map(|o| {
+                Ok((
+                    parse_time(&o.start)?,
+                    parse_time(&o.end)?,
+                    receiver(&o.receiver)?,
+                ))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Rotation {
+            receivers: config
+                .receivers
+                .iter()
+                .map(|r| receiver(r))
+                .collect::<Result<_, _>>()?,
+            name: config.name,
+            start: parse_time(&config.start)?,
+            handoff_days: config.handoff_days as i64,
+            overrides,
+        })
+    }
+    // 当前值班的渠道 临时换班优先
+    fn current(&self, now: u64) -> Option<usize> {
+        let now = Local.timestamp_opt(now as i64, 0).single()?.naive_local();
+        if let Some((_, _, r)) = self
+            .overrides
+            .iter()
+            .find(|(s, e, _)| *s <= now && now < *e)
+        {
+            return Some(*r);
+        }
+        let period = self.handoff_days * 24 * 60;
+        let turns = (now - self.start).num_minutes().div_euclid(period);
+        let i = turns.rem_euclid(self.receivers.len() as i64) as usize;
+        Some(self.receivers[i])
+    }
+}
+
+enum StepTo {
+    Rotation(usize),
+    Receiver(usize),
+}
+
+struct Step {
+    delay_secs: u64,
+    to: StepTo,
+}
+
+struct Policy {
+    name: String,
+    steps: Vec<Step>,
+}
+
+// 启动时编译的轮值表与升级策略 receiver均为通知渠道的下标
+#[derive(Default)]
+pub struct Escalations {
+    rotations: Vec<Rotation>,
+    policies: Vec<Policy>,
+}
+
+impl Escalations {
+    // receivers为全部通知渠道的名称 配置有误时返回全部错误
+    pub fn new(config: OnCall, receivers: &[&str]) -> Result<Escalations, String> {
+        let receiver = |name: &str| {
+            receivers
+                .iter()
+                .position(|r| *r == name)
+                .ok_or(format!("unknown receiver {}", name))
+        };
+        let mut errors = Vec::new();
+        let mut rotations = Vec::new();
+        for schedule in config.schedules {
+            let name = schedule.name.clone();
+            match Rotation::new(schedule, &receiver) {
+                Ok(rotation) => rotations.push(rotation),
+                Err(e) => errors.push(format!("schedule {}: {}", name, e)),
+            }
+        }
+        let mut escalations = Escalations {
+            rotations,
+            policies: Vec::new(),
+        };
+        for escalation in config.escalations {
+            let name = escalation.name.clone();
+            match escalations.compile(escalation, &receiver) {
+                Ok(policy) => escalations.policies.push(policy),
+                Err(e) => errors.push(format!("escalation {}: {}", name, e)),
+            }
+        }
+        if errors.is_empty() {
+            Ok(escalations)
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+    fn compile(
+        &self,
+        config: Escalation,
+        receiver: &impl Fn(&str) -> Result<usize, String>,
+    ) -> Result<Policy, String> {
+        if config.steps.is_empty() {
+            return Err("steps is empty".to_string());
+        }
+        let mut steps: Vec<Step> = Vec::new();
+        for (i, step) in config.steps.into_iter().enumerate() {
+            if steps.last().is_some_and(|s| s.delay_secs > step.delay_secs) {
+                return Err(format!(
+                    "steps[{}]: delay_secs is less than the previous step",
+                    i
+                ));
+            }
+            let to = match (step.schedule, step.receiver) {
+                (Some(schedule), None) => StepTo::Rotation(
+                    self.rotations
+                        .iter()
+                        .position(|r| r.name == schedule)
+                        .ok_or(format!("steps[{}]: unknown schedule {}", i, schedule))?,
+                ),
+                (None, Some(name)) => {
+                    StepTo::Receiver(receiver(&name).map_err(|e| format!("steps[{}]: {}", i, e))?)
+                }
+                _ => {
+                    return Err(format!(
+                        "steps[{}]: exactly one of schedule and receiver is required",
+                        i
+                    ))
+                }
+            };
+            steps.push(Step {
+                delay_secs: step.delay_secs,
+                to,
+            });
+        }
+        Ok(Policy {
+            name: config.name,
+            steps,
+        })
+    }
+    // 第step级的延迟 step为0时即第一级之前 为0
+    pub fn delay(&self, policy: usize, step: usize) -> u64 {
+        step.checked_sub(1)
+            .and_then(|i| self.policies[policy].steps.get(i))
+            .map_or(0, |s| s.delay_secs)
+    }
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.policies.iter().position(|p| p.name == name)
+    }
+    // 从第from级起 告警已持续elapsed秒时到期的各级
+    // 返回下一个未到期的级别以及需要通知的渠道
+    pub fn due(&self, policy: usize, from: usize, elapsed: u64, now: u64) -> (usize, Vec<usize>) {
+        let steps = &self.policies[policy].steps;
+        let mut next = from;
+        let mut receivers = Vec::new();
+        while next < steps.len() && steps[next].delay_secs <= elapsed {
+            let receiver = match steps[next].to {
+                StepTo::Rotation(i) => self.rotations[i].current(now),
+                StepTo::Receiver(i) => Some(i),
+            };
+            receivers.extend(receiver);
+            next += 1;
+        }
+        (next, receivers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECEIVERS: [&str; 4] = ["alice", "bob", "carol", "lead"];
+
+    fn at(s: &str) -> u64 {
+        let t = parse_time(s).unwrap();
+        Local.from_local_datetime(&t).unwrap().timestamp() as u64
+    }
+
+    fn escalations() -> Escalations {
+        let config: OnCall = serde_yaml::from_str(
+            r#"
+schedules:
+  - name: primary
+    receivers: [alice, bob]
+    start: "2023-01-02 09:00"
+    overrides:
+      - {receiver: carol, start: "2023-01-04 00:00", end: "2023-01-05 00:00"}
+  - name: secondary
+    receivers: [bob, alice]
+    start: "2023-01-02 09:00"
+escalations:
+  - name: db
+    steps:
+      - {schedule: primary}
+      - {schedule: secondary, delay_secs: 600}
+      - {receiver: lead, delay_secs: 1800}
+"#,
+        )
+        .unwrap();
+        Escalations::new(config, &RECEIVERS).unwrap()
+    }
+
+    #[test]
+    fn rotations_hand_off_weekly_with_overrides() {
+        let escalations = escalations();
+        let primary = &escalations.rotations[0];
+        assert_eq!(primary.current(at("2023-01-02 09:00")), Some(0));
+        assert_eq!(primary.current(at("2023-01-09 08:59")), Some(0));
+        assert_eq!(primary.current(at("2023-01-09 09:00")), Some(1));
+        assert_eq!(primary.current(at("2023-01-16 09:00")), Some(0));
+        // 开始之前的周期同样轮转
+        assert_eq!(primary.current(at("2023-01-01 09:00")), Some(1));
+        assert_eq!(primary.current(at("2023-01-02 08:59")), Some(1));
+        assert_eq!(primary.current(at("2023-01-04 12:00")), Some(2));
+    }
+
+    #[test]
+    fn steps_are_due_by_delay() {
+        let escalations = escalations();
+        let db = escalations.find("db").unwrap();
+        let now = at("2023-01-03 12:00");
+        assert_eq!(escalations.due(db, 0, 0, now), (1, vec![0]));
+        assert_eq!(escalations.due(db, 1, 599, now), (1, vec![]));
+        assert_eq!(escalations.due(db, 1, 600, now), (2, vec![1]));
+        // 长时间未处理时一次升级到底
+        assert_eq!(escalations.due(db, 0, 3600, now), (3, vec![0, 1, 3]));
+        assert_eq!(escalations.due(db, 3, 7200, now), (3, vec![]));
+    }
+
+    #[test]
+    fn invalid_config_fails_at_startup() {
+        let config: OnCall = serde_yaml::from_str(
+            r#"
+schedules:
+  - {name: a, receivers: [nobody], start: "2023-01-02 09:00"}
+  - {name: b, receivers: [alice], start: "monday"}
+escalations:
+  - name: c
+    steps: [{schedule: missing}]
+  - name: d
+    steps: [{receiver: alice, delay_secs: 60}, {receiver: bob}]
+  - name: e
+    steps: [{}]
+"#,
+        )
+        .unwrap();
+        let err = Escalations::new(config, &RECEIVERS).err().unwrap();
+        assert!(err.contains("schedule a: unknown receiver nobody"));
+        assert!(err.contains("schedule b: bad time monday"));
+        assert!(err.contains("escalation c: steps[0]: unknown schedule missing"));
+        assert!(err.contains("escalation d: steps[1]: delay_secs"));
+        assert!(err.contains("escalation e: steps[0]: exactly one"));
+    }
+}
@@ -0,0 +1,222 @@
+use crate::core::ent::timestamp;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+// 重试间隔的上限
+pub const MAX_BACKOFF_SECS: u64 = 3600;
+
+// 第attempts次投递失败后的重试间隔 从base_secs起指数退避
+pub fn backoff(base_secs: u64, attempts: u32) -> u64 {
+    base_secs
+        .saturating_mul(1 << attempts.saturating_sub(1).min(16))
+        .min(MAX_BACKOFF_SECS)
+}
+
+// 待投递的一封通知 以json文件的形式保存在发件箱目录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Letter {
+    pub id: String,
+    pub subject: String,
+    pub body: String,
+    #[serde(default)]
+    pub html: Option<String>,
+    pub created_at: u64,
+    pub attempts: u32,
+    pub next_attempt: u64,
+    pub last_error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Delivery<'a> {
+    time: u64,
+    id: &'a str,
+    subject: &'a str,
+    status: &'a str,
+    attempts: u32,
+    error: Option<&'a str>,
+}
+
+// 落盘的发件箱 进程重启后未投递成功的通知会继续重试
+// dir/<id>.json 待投递 dir/dead/<id>.json 放弃投递 dir/delivery.log 投递记录
+#[derive(Debug)]
+pub struct Outbox {
+    dir: PathBuf,
+}
+
+impl Outbox {
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Outbox> {
+        let dir = dir.into();
+        fs::create_dir_all(dir.join("dead"))?;
+        Ok(Outbox { dir })
+    }
+    // 先写临时文件再改名 避免进程中断留下半个文件
+    pub fn save(&self, letter: &Letter) -> io::Result<()> {
+        let tmp = self.dir.join(format!("{}.tmp", letter.id));
+        fs::write(&tmp, serde_json::to_vec_pretty(letter)?)?;
+        fs::rename(tmp, self.path(&letter.id))
+    }
+    pub fn pending(&self) -> Vec<Letter> {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        let mut letters: Vec<Letter> = entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|p| match fs::read(&p).map(|b| serde_json::from_slice(&b)) {
+                Ok(Ok(letter)) => Some(letter),
+                _ => {
+                    tracing::error!("outbox: skip unreadable letter {:?}", p);
+                    None
+                }
+            })
+            .collect();
+        letters.sort_by_key(|l: &Letter| l.created_at);
+        letters
+    }
+    pub fn remove(&self, id: &str) -> io::Result<()> {
+        fs::remove_file(self.path(id))
+    }
+    // 记下一次投递失败 未超过重试次数时保存等待退避后重试 否则移入dead目录
+    // 返回是否已放弃投递
+    pub fn fail(
+        &self,
+        letter: &mut Letter,
+        error: String,
+        max_retries: u32,
+        backoff_secs: u64,
+        now: u64,
+    ) -> io::Result<bool> {
+        letter.last_error = Some(error);
+        if letter.attempts >= max_retries {
+            self.record(letter, "dead");
+            self.bury(letter)?;
+            return Ok(true);
+        }
+        letter.next_attempt = now + backoff(backoff_secs, letter.attempts);
+        self.record(letter, "retry");
+        self.save(letter)?;
+        Ok(false)
+    }
+    // 移入dead目录留档 先保存以记下最后的尝试次数与错误
+    pub fn bury(&self, letter: &Letter) -> io::Result<()> {
+        self.save(letter)?;
+        fs::rename(
+            self.path(&letter.id),
+            self.dir.join("dead").join(format!("{}.json", letter.id)),
+        )
+    }
+    // 追加一条投递记录
+    pub fn record(&self, letter: &Letter, status: &str) {
+        let line = serde_json::to_string(&Delivery {
+            time: timestamp(),
+            id: &letter.id,
+            subject: &letter.subject,
+            status,
+            attempts: letter.attempts,
+            error: letter.last_error.as_deref(),
+        })
+        .unwrap();
+        let result = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join("delivery.log"))
+            .and_then(|mut f| writeln!(f, "{}", line));
+        if let Err(e) = result {
+            tracing::error!("outbox: write delivery log fail {:?}", e);
+        }
+    }
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn letter(id: &str, created_at: u64) -> Letter {
+        Letter {
+            id: id.to_string(),
+            subject: format!("alert {}", id),
+            body: "disk > 90%".to_string(),
+            html: None,
+            created_at,
+            attempts: 0,
+            next_attempt: created_at,
+            last_error: None,
+        }
+    }
+
+    fn outbox() -> (PathBuf, Outbox) {
+        let dir = std::env::temp_dir().join(format!("outbox-{}", Uuid::new_v4()));
+        let outbox = Outbox::open(&dir).unwrap();
+        (dir, outbox)
+    }
+
+    #[test]
+    fn letters_survive_reopen() {
+        let (dir, outbox) = outbox();
+        outbox.save(&letter("b", 200)).unwrap();
+        outbox.save(&letter("a", 100)).unwrap();
+        let pending = outbox.pending();
+        assert_eq!(
+            pending.iter().map(|l| l.id.as_str()).collect::<Vec<_>>(),
+            vec!["a", "b"]
+        );
+        assert_eq!(pending[0].subject, "alert a");
+
+        // 重启后仍可继续投递
+        let outbox = Outbox::open(&dir).unwrap();
+        assert_eq!(outbox.pending().len(), 2);
+        outbox.remove("a").unwrap();
+        assert_eq!(Outbox::open(&dir).unwrap().pending().len(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_limit() {
+        assert_eq!(backoff(30, 1), 30);
+        assert_eq!(backoff(30, 2), 60);
+        assert_eq!(backoff(30, 3), 120);
+        assert_eq!(backoff(30, 8), MAX_BACKOFF_SECS);
+        assert_eq!(backoff(30, 100), MAX_BACKOFF_SECS);
+    }
+
+    #[test]
+    fn failed_letters_retry_then_are_buried() {
+        let (dir, outbox) = outbox();
+        let mut l = letter("a", 100);
+        outbox.save(&l).unwrap();
+        l.attempts += 1;
+        assert!(!outbox
+            .fail(&mut l, "timeout".to_string(), 3, 30, 1000)
+            .unwrap());
+        l.attempts += 1;
+        assert!(!outbox
+            .fail(&mut l, "timeout".to_string(), 3, 30, 1100)
+            .unwrap());
+        let saved = &outbox.pending()[0];
+        assert_eq!((saved.attempts, saved.next_attempt), (2, 1160));
+
+        l.attempts += 1;
+        assert!(outbox
+            .fail(&mut l, "refused".to_string(), 3, 30, 1200)
+            .unwrap());
+        assert!(outbox.pending().is_empty());
+        // dead目录中保留最后的尝试次数与错误
+        let dead: Letter =
+            serde_json::from_slice(&fs::read(dir.join("dead").join("a.json")).unwrap()).unwrap();
+        assert_eq!(dead.attempts, 3);
+        assert_eq!(dead.last_error.as_deref(), Some("refused"));
+        let log = fs::read_to_string(dir.join("delivery.log")).unwrap();
+        assert_eq!(log.lines().count(), 3);
+        assert!(log.lines().last().unwrap().contains(r#""status":"dead""#));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,292 @@
+use crate::config::model::{Opsgenie, Pagerduty};
+use crate::core::ent::{AlertState, HealthInfo, HealthStatus};
+use crate::core::notifier::Notifier;
+use crate::core::render::{link, EventView};
+use crate::core::webhook::{accept_any, deliver, http_client};
+use reqwest::{Client, Request, Url};
+use serde_json::{json, Map};
+
+// 事件来源 用于告警平台上的展示
+const SOURCE: &str = "health-checker";
+
+// 告警平台 firing时创建/更新告警 resolved时关闭 同一告警以dedup_key合并
+pub enum Pager {
+    PagerDuty { routing_key: String },
+    Opsgenie { api_key: String },
+}
+
+pub struct PagerNotifier {
+    name: String,
+    pager: Pager,
+    url: Url,
+    public_url: Option<String>,
+    retries: u32,
+    client: Client,
+}
+
+impl PagerNotifier {
+    pub fn pagerduty(
+        name: String,
+        config: Pagerduty,
+        public_url: Option<String>,
+    ) -> Result<PagerNotifier, String> {
+        let url =
+            Url::parse(&config.url).map_err(|e| format!("pagerduty {}: bad url: {}", name, e))?;
+        Ok(PagerNotifier {
+            name,
+            pager: Pager::PagerDuty {
+                routing_key: config.routing_key,
+            },
+            url,
+            public_url,
+            retries: config.retries,
+            client: http_client(config.timeout_secs),
+        })
+    }
+    pub fn opsgenie(
+        name: String,
+        config: Opsgenie,
+        public_url: Option<String>,
+    ) -> Result<PagerNotifier, String> {
+        let url =
+            Url::parse(&config.url).map_err(|e| format!("opsgenie {}: bad url: {}", name, e))?;
+        Ok(PagerNotifier {
+            name,
+            pager: Pager::Opsgenie {
+                api_key: config.api_key,
+            },
+            url,
+            public_url,
+            retries: config.retries,
+            client: http_client(config.timeout_secs),
+        })
+    }
+    // 单个事件对应的请求 pending或没有告警信息的事件不发送
+    fn request(&self, info: &HealthInfo) -> Option<Result<Request, reqwest::Error>> {
+        let state = info.alert.as_ref()?.state;
+        let dedup_key = info.dedup_key()?;
+        let view = EventView::new(info);
+        let summary = format!("{} {} {}", view.label(), view.kind, view.name);
+        let href = self.public_url.as_deref().map(|base| link(base, info));
+        let mut details = Map::new();
+        details.insert("msg".to_string(), json!(view.msg));
+        for f in &view.metrics {
+            details.insert(f.name.to_string(), json!(f.value));
+        }
+        let request = match (&self.pager, state) {
+            (_, AlertState::Pending) => return None,
+            (Pager::PagerDuty { routing_key }, AlertState::Firing | AlertState::Flapping) => {
+                let mut body = json!({
+                    "routing_key": routing_key,
+                    "event_action": "trigger",
+                    "dedup_key": dedup_key,
+                    "payload": {
+                        "summary": summary,
+                        "source": SOURCE,
+                        "severity": match info.status {
+                            HealthStatus::Red => "critical",
+                            _ => "warning",
+                        },
+                        "component": view.name,
+                        "class": view.kind,
+                        "custom_details": details,
+                    },
+                });
+                if let Some(href) = href {
+                    body["links"] = json!([{"href": href, "text": format!("View {}", view.name)}]);
+                }
+                self.client.post(self.url.clone()).json(&body).build()
+            }
+            (Pager::PagerDuty { routing_key }, AlertState::Resolved) => {
+                let body = json!({
+                    "routing_key": routing_key,
+                    "event_action": "resolve",
+                    "dedup_key": dedup_key,
+                });
+                self.client.post(self.url.clone()).json(&body).build()
+            }
+            (Pager::Opsgenie { api_key }, AlertState::Firing | AlertState::Flapping) => {
+                if let Some(href) = href {
+                    details.insert("link".to_string(), json!(href));
+                }
+                let body = json!({
+                    "message": summary,
+                    "alias": dedup_key,
+                    "description": view.msg,
+                    "source": SOURCE,
+                    "entity": view.name,
+                    "priority": match info.status {
+                        HealthStatus::Red => "P1",
+                        _ => "P3",
+                    },
+                    "details": details,
+                });
+                self.client
+                    .post(self.url.clone())
+                    .header("Authorization", format!("GenieKey {}", api_key))
+                    .json(&body)
+                    .build()
+            }
+            (Pager::Opsgenie { api_key }, AlertState::Resolved) => {
+                let mut url = self.url.clone();
+                url.path_segments_mut()
+                    .expect("http url has path")
+                    .push(&dedup_key)
+                    .push("close");
+                url.query_pairs_mut().append_pair("identifierType", "alias");
+                let body = json!({"source": SOURCE, "note": view.msg});
+                self.client
+                    .post(url)
+                    .header("Authorization", format!("GenieKey {}", api_key))
+                    .json(&body)
+                    .build()
+            }
+        };
+        Some(request)
+    }
+}
+
+impl Notifier for PagerNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn notify(&self, events: Vec<HealthInfo>) {
+        for info in &events {
+            match self.request(info) {
+                Some(Ok(request)) => {
+                    tokio::spawn(deliver(
+                        self.client.clone(),
+                        format!("pager {}", self.name),
+                        request,
+                        self.retries,
+                        accept_any,
+                    ));
+                }
+                Some(Err(e)) => tracing::error!("pager {}: build request fail: {}", self.name, e),
+                None => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ent::*;
+    use axum::{
+        extract::State,
+        http::{StatusCode, Uri},
+        routing::post,
+        Json,
+    };
+    use serde_json::Value;
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+    use tokio::time;
+
+    type Hits = Arc<Mutex<Vec<(String, Value)>>>;
+
+    async fn hook(State(hits): State<Hits>, uri: Uri, Json(body): Json<Value>) -> StatusCode {
+        hits.lock().unwrap().push((uri.to_string(), body));
+        StatusCode::ACCEPTED
+    }
+
+    async fn serve(hits: Hits) -> String {
+        let app = axum::Router::new()
+            .route("/v2/enqueue", post(hook))
+            .route("/v2/alerts", post(hook))
+            .route("/v2/alerts/*alias", post(hook))
+            .with_state(hits);
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        format!("http://{}", addr)
+    }
+
+    async fn wait(hits: &Hits, n: usize) -> Vec<(String, Value)> {
+        for _ in 0..50 {
+            if hits.lock().unwrap().len() >= n {
+                break;
+            }
+            time::sleep(Duration::from_millis(100)).await;
+        }
+        hits.lock().unwrap().clone()
+    }
+
+    fn event(state: AlertState) -> HealthInfo {
+        HealthInfo {
+            target: Target::Node("db-1".to_string(), None),
+            status: HealthStatus::Red,
+            alert: Some(Alert {
+                id: "a1".to_string(),
+                rule: "disk_per".to_string(),
+                state,
+                since: 0,
+                last_notified: None,
+                ack_url: None,
+                acked_by: None,
+                acked_at: None,
+            }),
+        }
+    }
+
+    #[tokio::test]
+    async fn pagerduty_triggers_and_resolves_with_dedup_key() {
+        let hits: Hits = Arc::default();
+        let base = serve(hits.clone()).await;
+        let config: Pagerduty = serde_json::from_value(json!({
+            "routing_key": "R0UT1NG",
+            "url": format!("{}/v2/enqueue", base),
+        }))
+        .unwrap();
+        let pd = PagerNotifier::pagerduty(
+            "pd".to_string(),
+            config,
+            Some("http://monitor:3000".to_string()),
+        )
+        .unwrap();
+        pd.notify(vec![event(AlertState::Pending)]);
+        pd.notify(vec![event(AlertState::Firing)]);
+        wait(&hits, 1).await;
+        pd.notify(vec![event(AlertState::Resolved)]);
+        let hits = wait(&hits, 2).await;
+        assert_eq!(hits.len(), 2);
+        let trigger = &hits[0].1;
+        assert_eq!(trigger["event_action"], "trigger");
+        assert_eq!(trigger["dedup_key"], "node/db-1/disk_per");
+        assert_eq!(trigger["payload"]["severity"], "critical");
+        assert_eq!(
+            trigger["links"][0]["href"],
+            "http://monitor:3000/nodes/db-1"
+        );
+        let resolve = &hits[1].1;
+        assert_eq!(resolve["event_action"], "resolve");
+        assert_eq!(resolve["dedup_key"], "node/db-1/disk_per");
+    }
+
+    #[tokio::test]
+    async fn opsgenie_creates_and_closes_by_alias() {
+        let hits: Hits = Arc::default();
+        let base = serve(hits.clone()).await;
+        let config: Opsgenie = serde_json::from_value(json!({
+            "api_key": "k",
+            "url": format!("{}/v2/alerts", base),
+        }))
+        .unwrap();
+        let og = PagerNotifier::opsgenie("og".to_string(), config, None).unwrap();
+        og.notify(vec![event(AlertState::Firing)]);
+        wait(&hits, 1).await;
+        og.notify(vec![event(AlertState::Resolved)]);
+        let hits = wait(&hits, 2).await;
+        assert_eq!(hits[0].0, "/v2/alerts");
+        assert_eq!(hits[0].1["alias"], "node/db-1/disk_per");
+        assert_eq!(hits[0].1["priority"], "P1");
+        assert_eq!(
+            hits[1].0,
+            "/v2/alerts/node%2Fdb-1%2Fdisk_per/close?identifierType=alias"
+        );
+    }
+}
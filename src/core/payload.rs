@@ -0,0 +1,173 @@
+use crate::config::model::CheckKind;
+use crate::core::ent::{Component, HealthStatus};
+use serde_json::{Map, Value};
+
+// 解析结构化的健康接口 返回整体状态与各组件的状态 无法解析时返回None
+pub fn parse(kind: CheckKind, body: &[u8]) -> Option<(Component, Vec<Component>)> {
+    let json: Value = serde_json::from_slice(body).ok()?;
+    let status = json.get("status")?.as_str()?;
+    let mut components = Vec::new();
+    let overall = match kind {
+        CheckKind::Http | CheckKind::Tcp => return None,
+        CheckKind::Actuator => {
+            actuator_components("", &json, &mut components);
+            component("", status, actuator_health(status), None)
+        }
+        CheckKind::HealthJson => {
+            if let Some(checks) = json.get("checks").and_then(Value::as_object) {
+                health_json_checks(checks, &mut components);
+            }
+            let output = json.get("output").and_then(Value::as_str);
+            component("", status, health_json_health(status), output)
+        }
+    };
+    Some((overall, components))
+}
+
+fn component(name: &str, status: &str, health: HealthStatus, detail: Option<&str>) -> Component {
+    Component {
+        name: name.to_string(),
+        status: status.to_string(),
+        health,
+        detail: detail.filter(|d| !d.is_empty()).map(String::from),
+    }
+}
+
+// OUT_OF_SERVICE多为主动下线 与UNKNOWN一样只警告
+fn actuator_health(status: &str) -> HealthStatus {
+    match status.to_uppercase().as_str() {
+        "UP" => HealthStatus::Green,
+        "DOWN" => HealthStatus::Red,
+        _ => HealthStatus::Yellow,
+    }
+}
+
+fn health_json_health(status: &str) -> HealthStatus {
+    match status.to_lowercase().as_str() {
+        "pass" | "ok" | "up" => HealthStatus::Green,
+        "warn" => HealthStatus::Yellow,
+        _ => HealthStatus::Red,
+    }
+}
+
+// 2.2起组件在components下 2.0/2.1在details下 1.x直接与status并列
+// 带有status字段的对象视为组件 组合组件递归展开
+fn actuator_components(prefix: &str, json: &Value, out: &mut Vec<Component>) {
+    let parent = json
+        .get("components")
+        .or_else(|| json.get("details"))
+        .unwrap_or(json);
+    let Some(parent) = parent.as_object() else {
+        return;
+    };
+    for (name, value) in parent {
+        let Some(status) = value.get("status").and_then(Value::as_str) else {
+            continue;
+        };
+        let name = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+        let detail = value.pointer("/details/error").and_then(Value::as_str);
+        out.push(component(&name, status, actuator_health(status), detail));
+        actuator_components(&name, value, out);
+    }
+}
+
+// checks的键为 组件名:度量名 同一个键下可有多个实例 以componentId区分
+fn health_json_checks(checks: &Map<String, Value>, out: &mut Vec<Component>) {
+    for (key, results) in checks {
+        let Some(results) = results.as_array() else {
+            continue;
+        };
+        for result in results {
+            let Some(status) = result.get("status").and_then(Value::as_str) else {
+                continue;
+            };
+            let name = match result.get("componentId").and_then(Value::as_str) {
+                Some(id) if results.len() > 1 => format!("{}/{}", key, id),
+                _ => key.clone(),
+            };
+            let output = result.get("output").and_then(Value::as_str);
+            out.push(component(&name, status, health_json_health(status), output));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(components: &[Component]) -> Vec<(&str, &str, HealthStatus)> {
+        components
+            .iter()
+            .map(|c| (c.name.as_str(), c.status.as_str(), c.health))
+            .collect()
+    }
+
+    #[test]
+    fn parses_actuator_components() {
+        let body = br#"{
+            "status": "DOWN",
+            "components": {
+                "db": {"status": "UP", "components": {
+                    "primary": {"status": "UP", "details": {"database": "PostgreSQL"}},
+                    "replica": {"status": "OUT_OF_SERVICE"}
+                }},
+                "diskSpace": {"status": "UP", "details": {"total": 1, "free": 1}},
+                "redis": {"status": "DOWN", "details": {"error": "Connection refused"}}
+            }
+        }"#;
+        let (overall, components) = parse(CheckKind::Actuator, body).unwrap();
+        assert_eq!(overall.health, HealthStatus::Red);
+        assert_eq!(
+            summary(&components),
+            vec![
+                ("db", "UP", HealthStatus::Green),
+                ("db/primary", "UP", HealthStatus::Green),
+                ("db/replica", "OUT_OF_SERVICE", HealthStatus::Yellow),
+                ("diskSpace", "UP", HealthStatus::Green),
+                ("redis", "DOWN", HealthStatus::Red),
+            ]
+        );
+        assert_eq!(components[4].detail.as_deref(), Some("Connection refused"));
+
+        // 2.0/2.1的格式
+        let body = br#"{"status":"UP","details":{"db":{"status":"UP","details":{"hello":1}}}}"#;
+        let (_, components) = parse(CheckKind::Actuator, body).unwrap();
+        assert_eq!(
+            summary(&components),
+            vec![("db", "UP", HealthStatus::Green)]
+        );
+        assert!(parse(CheckKind::Actuator, b"ok").is_none());
+        assert!(parse(CheckKind::Http, br#"{"status":"UP"}"#).is_none());
+    }
+
+    #[test]
+    fn parses_health_json_checks() {
+        let body = br#"{
+            "status": "warn",
+            "output": "degraded",
+            "checks": {
+                "cassandra:responseTime": [
+                    {"componentId": "c1", "status": "pass"},
+                    {"componentId": "c2", "status": "fail", "output": "timeout"}
+                ],
+                "uptime": [{"status": "pass"}]
+            }
+        }"#;
+        let (overall, components) = parse(CheckKind::HealthJson, body).unwrap();
+        assert_eq!(overall.health, HealthStatus::Yellow);
+        assert_eq!(overall.detail.as_deref(), Some("degraded"));
+        assert_eq!(
+            summary(&components),
+            vec![
+                ("cassandra:responseTime/c1", "pass", HealthStatus::Green),
+                ("cassandra:responseTime/c2", "fail", HealthStatus::Red),
+                ("uptime", "pass", HealthStatus::Green),
+            ]
+        );
+        assert_eq!(components[1].detail.as_deref(), Some("timeout"));
+    }
+}
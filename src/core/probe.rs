@@ -0,0 +1,50 @@
+use crate::core::ent::Timings;
+use reqwest::Url;
+use std::time::Instant;
+use tokio::{
+    net::{lookup_host, TcpStream},
+    time::{self, timeout_at},
+};
+use tokio_native_tls::TlsConnector;
+
+// reqwest不提供各阶段的耗时 开启probe_phases的服务在请求前单独建立一次连接测量DNS解析、TCP连接与TLS握手
+// 结果是独立的一次预探测 不是请求总耗时的拆分
+// 与请求共用同一个截止时间 某一阶段失败时返回已完成阶段的耗时与错误 不必再发请求
+pub async fn phases(
+    url: &Url,
+    deadline: time::Instant,
+    tls: &TlsConnector,
+) -> Result<Timings, (Timings, String)> {
+    let mut timings = Timings::default();
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        return Ok(timings);
+    };
+    // IPv6地址带有方括号
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let start = Instant::now();
+    let mut addrs = match timeout_at(deadline, lookup_host((host, port))).await {
+        Ok(Ok(addrs)) => addrs,
+        Ok(Err(e)) => return Err((timings, format!("resolve {} fail: {}", host, e))),
+        Err(_) => return Err((timings, format!("resolve {} timeout", host))),
+    };
+    timings.dns = Some(start.elapsed().as_millis());
+    let Some(addr) = addrs.next() else {
+        return Err((timings, format!("resolve {} fail: no address", host)));
+    };
+    let start = Instant::now();
+    let stream = match timeout_at(deadline, TcpStream::connect(addr)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => return Err((timings, format!("connect {} fail: {}", addr, e))),
+        Err(_) => return Err((timings, format!("connect {} timeout", addr))),
+    };
+    timings.connect = Some(start.elapsed().as_millis());
+    if url.scheme() == "https" {
+        let start = Instant::now();
+        match timeout_at(deadline, tls.connect(host, stream)).await {
+            Ok(Ok(_)) => timings.tls = Some(start.elapsed().as_millis()),
+            Ok(Err(e)) => return Err((timings, format!("tls handshake fail: {}", e))),
+            Err(_) => return Err((timings, "tls handshake timeout".to_string())),
+        }
+    }
+    Ok(timings)
+}
@@ -0,0 +1,223 @@
+use crate::core::ent::*;
+use chrono::{Local, TimeZone, Utc};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::Serialize;
+
+// 模板的渲染参数 events为整理后的展示数据 health_infos为原始的事件
+#[derive(Serialize)]
+pub struct RenderContext<'a> {
+    pub update_time: String,
+    pub events: Vec<EventView>,
+    pub health_infos: &'a [HealthInfo],
+}
+
+impl<'a> RenderContext<'a> {
+    pub fn new(events: &'a [HealthInfo]) -> RenderContext<'a> {
+        RenderContext {
+            update_time: Utc::now().to_rfc3339(),
+            events: views(events),
+            health_infos: events,
+        }
+    }
+}
+
+// 供模板/消息卡片使用的扁平化事件
+#[derive(Debug, Serialize)]
+pub struct EventView {
+    pub kind: &'static str,
+    pub name: String,
+    pub status: HealthStatus,
+    pub state: Option<AlertState>,
+    pub color: &'static str,
+    pub msg: String,
+    pub metrics: Vec<Field>,
+    pub ack_url: Option<String>, //一键确认链接 仅firing且配置了对外地址时存在
+}
+
+#[derive(Debug, Serialize)]
+pub struct Field {
+    pub name: &'static str,
+    pub value: String,
+}
+
+fn field(name: &'static str, value: impl ToString) -> Field {
+    Field {
+        name,
+        value: value.to_string(),
+    }
+}
+
+pub fn color(status: HealthStatus) -> &'static str {
+    match status {
+        HealthStatus::Red => "#d9534f",
+        HealthStatus::Yellow => "#f0ad4e",
+        HealthStatus::Green => "#5cb85c",
+    }
+}
+
+pub fn format_time(ts: u64) -> String {
+    Local
+        .timestamp_opt(ts as i64, 0)
+        .single()
+        .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+impl EventView {
+    pub fn new(info: &HealthInfo) -> EventView {
+        let (kind, name, metrics) = match &info.target {
+            Target::Node(id, node) => (
+                "node",
+                id.clone(),
+                node.as_ref().map_or_else(Vec::new, |n| {
+                    vec![
+                        field("ip", &n.system_ip),
+                        field("disk", format!("{}% ({})", n.disk_per, n.disk_f)),
+                        field(
+                            "mem",
+                            format!("{}% of {}", n.mem_status_per, n.mem_status_total),
+                        ),
+                        field(
+                            "load",
+                            format!("{} / {} / {}", n.load_1, n.load_5, n.load_15),
+                        ),
+                        field("updated", format_time(n.last_updated)),
+                    ]
+                }),
+            ),
+            Target::Service(name, srv) => (
+                "service",
+                name.clone(),
+                srv.as_ref().map_or_else(Vec::new, |s| {
+                    let mut fields = vec![
+                        field("api", &s.api),
+                        field("latency", format!("{} ms", s.latency)),
+                    ];
+                    let t = &s.timings;
+                    let phases: Vec<String> =
+                        [("dns", t.dns), ("connect", t.connect), ("tls", t.tls)]
+                            .iter()
+                            .filter_map(|(name, ms)| ms.map(|ms| format!("{} {} ms", name, ms)))
+                            .collect();
+                    if !phases.is_empty() {
+                        fields.push(field("phases", phases.join(" / ")));
+                    }
+                    if !s.components.is_empty() {
+                        let components: Vec<String> = s
+                            .components
+                            .iter()
+                            .map(|c| format!("{} {}", c.name, c.status))
+                            .collect();
+                        fields.push(field("components", components.join(", ")));
+                    }
+                    fields.push(field("updated", format_time(s.last_updated)));
+                    fields
+                }),
+            ),
+        };
+        EventView {
+            kind,
+            name,
+            status: info.status,
+            state: info.alert.as_ref().map(|a| a.state),
+            color: color(info.status),
+            msg: info.msg().to_string(),
+            metrics,
+            ack_url: info.alert.as_ref().and_then(|a| a.ack_url.clone()),
+        }
+    }
+    // 形如 [Red / Firing] 的状态标签
+    pub fn label(&self) -> String {
+        match self.state {
+            Some(state) => format!("[{:?} / {:?}]", self.status, state),
+            None => format!("[{:?}]", self.status),
+        }
+    }
+}
+
+// 路径段中保留的字符之外一律编码 节点id与服务名可能含有空格、/、?等
+const SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+// 目标在本服务API中的地址 base为对外暴露的服务地址
+pub fn link(base: &str, info: &HealthInfo) -> String {
+    let base = base.trim_end_matches('/');
+    match &info.target {
+        Target::Node(id, _) => format!("{}/nodes/{}", base, utf8_percent_encode(id, SEGMENT)),
+        Target::Service(name, _) => {
+            format!("{}/services/{}", base, utf8_percent_encode(name, SEGMENT))
+        }
+    }
+}
+
+pub fn views(events: &[HealthInfo]) -> Vec<EventView> {
+    events.iter().map(EventView::new).collect()
+}
+
+// 一批事件中最严重的状态
+pub fn worst(events: &[HealthInfo]) -> HealthStatus {
+    events
+        .iter()
+        .map(|e| e.status)
+        .min_by_key(|s| match s {
+            HealthStatus::Red => 0,
+            HealthStatus::Yellow => 1,
+            HealthStatus::Green => 2,
+        })
+        .unwrap_or(HealthStatus::Green)
+}
+
+pub fn title(events: &[HealthInfo]) -> String {
+    format!("[{:?}] 资源监控预警", worst(events))
+}
+
+// 渲染为markdown 各平台给文字着色的语法不同 由paint决定
+pub fn markdown(events: &[HealthInfo], paint: impl Fn(HealthStatus, &str) -> String) -> String {
+    let mut md = String::new();
+    for view in views(events) {
+        md.push_str(&format!(
+            "**{} {} {}**\n",
+            paint(view.status, &view.label()),
+            view.kind,
+            view.name
+        ));
+        for f in &view.metrics {
+            md.push_str(&format!("- {}: {}\n", f.name, f.value));
+        }
+        if !view.msg.is_empty() {
+            md.push_str(&format!("> {}\n", view.msg.replace('\n', "\n> ")));
+        }
+        if let Some(url) = &view.ack_url {
+            md.push_str(&format!("[确认告警]({})\n", url));
+        }
+        md.push('\n');
+    }
+    md
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_encode_path_segment() {
+        let info = |target| HealthInfo {
+            target,
+            status: HealthStatus::Red,
+            alert: None,
+        };
+        let node = info(Target::Node("db-1.local".to_string(), None));
+        assert_eq!(
+            link("http://monitor/", &node),
+            "http://monitor/nodes/db-1.local"
+        );
+        let service = info(Target::Service("orders api/v2?x#1".to_string(), None));
+        assert_eq!(
+            link("http://monitor", &service),
+            "http://monitor/services/orders%20api%2Fv2%3Fx%231"
+        );
+    }
+}
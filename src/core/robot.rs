@@ -0,0 +1,222 @@
+use crate::config::model::Robot;
+use crate::core::ent::{HealthInfo, HealthStatus};
+use crate::core::notifier::Notifier;
+use crate::core::render::{color, markdown, title, worst};
+use crate::core::webhook::{deliver, hmac_sha256, http_client};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use reqwest::{Client, Url};
+use serde_json::{json, Value};
+
+// 国内常用IM的群机器人
+#[derive(Debug, Clone, Copy)]
+pub enum RobotKind {
+    DingTalk,
+    Feishu,
+    WeCom,
+}
+
+pub struct RobotNotifier {
+    name: String,
+    kind: RobotKind,
+    url: Url,
+    secret: Option<String>,
+    retries: u32,
+    client: Client,
+}
+
+impl RobotNotifier {
+    pub fn new(name: String, kind: RobotKind, config: Robot) -> Result<RobotNotifier, String> {
+        let url =
+            Url::parse(&config.url).map_err(|e| format!("{:?} {}: bad url: {}", kind, name, e))?;
+        // 企业微信机器人不支持加签
+        if matches!(kind, RobotKind::WeCom) && config.secret.is_some() {
+            return Err(format!("{:?} {}: secret is not supported", kind, name));
+        }
+        Ok(RobotNotifier {
+            name,
+            kind,
+            url,
+            secret: config.secret,
+            retries: config.retries,
+            client: http_client(config.timeout_secs),
+        })
+    }
+    // 返回加签后的地址与请求体
+    fn message(&self, events: &[HealthInfo]) -> (Url, Value) {
+        let title = title(events);
+        let mut url = self.url.clone();
+        match self.kind {
+            RobotKind::DingTalk => {
+                let text = format!("### {}\n\n{}", title, markdown(events, font_color));
+                // 加签: base64(hmac_sha256(secret, "{毫秒时间戳}\n{secret}"))
+                if let Some(secret) = &self.secret {
+                    let ts = Utc::now().timestamp_millis();
+                    let sign =
+                        hmac_sha256(secret.as_bytes(), format!("{}\n{}", ts, secret).as_bytes());
+                    url.query_pairs_mut()
+                        .append_pair("timestamp", &ts.to_string())
+                        .append_pair("sign", &STANDARD.encode(sign));
+                }
+                let body = json!({
+                    "msgtype": "markdown",
+                    "markdown": {"title": title, "text": text},
+                });
+                (url, body)
+            }
+            RobotKind::Feishu => {
+                let template = match worst(events) {
+                    HealthStatus::Red => "red",
+                    HealthStatus::Yellow => "orange",
+                    HealthStatus::Green => "green",
+                };
+                let mut body = json!({
+                    "msg_type": "interactive",
+                    "card": {
+                        "header": {
+                            "title": {"tag": "plain_text", "content": title},
+                            "template": template,
+                        },
+                        "elements": [{"tag": "markdown", "content": markdown(events, font_color)}],
+                    },
+                });
+                // 加签: base64(hmac_sha256("{秒级时间戳}\n{secret}", 空串))
+                if let Some(secret) = &self.secret {
+                    let ts = Utc::now().timestamp();
+                    let sign = hmac_sha256(format!("{}\n{}", ts, secret).as_bytes(), b"");
+                    body["timestamp"] = json!(ts.to_string());
+                    body["sign"] = json!(STANDARD.encode(sign));
+                }
+                (url, body)
+            }
+            RobotKind::WeCom => {
+                let content = format!("### {}\n{}", title, markdown(events, wecom_color));
+                let body = json!({
+                    "msgtype": "markdown",
+                    "markdown": {"content": content},
+                });
+                (url, body)
+            }
+        }
+    }
+}
+
+fn font_color(status: HealthStatus, text: &str) -> String {
+    format!("<font color={}>{}</font>", color(status), text)
+}
+
+// 企业微信只支持info/comment/warning三种颜色
+fn wecom_color(status: HealthStatus, text: &str) -> String {
+    let color = match status {
+        HealthStatus::Red => "warning",
+        HealthStatus::Yellow => "comment",
+        HealthStatus::Green => "info",
+    };
+    format!("<font color=\"{}\">{}</font>", color, text)
+}
+
+// 三家接口出错时大多仍返回200 需检查errcode/code
+fn check_errcode(body: &str) -> Result<(), String> {
+    let resp: Value =
+        serde_json::from_str(body).map_err(|e| format!("bad response {}: {}", body, e))?;
+    let code = resp
+        .get("errcode")
+        .or_else(|| resp.get("code"))
+        .or_else(|| resp.get("StatusCode"))
+        .and_then(Value::as_i64)
+        .unwrap_or(0);
+    if code == 0 {
+        Ok(())
+    } else {
+        Err(format!("robot error: {}", body))
+    }
+}
+
+impl Notifier for RobotNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn notify(&self, events: Vec<HealthInfo>) {
+        let (url, body) = self.message(&events);
+        match self.client.post(url).json(&body).build() {
+            Ok(request) => {
+                tokio::spawn(deliver(
+                    self.client.clone(),
+                    format!("{:?} {}", self.kind, self.name),
+                    request,
+                    self.retries,
+                    check_errcode,
+                ));
+            }
+            Err(e) => tracing::error!("{:?} {}: build request fail: {}", self.kind, self.name, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ent::*;
+
+    fn robot(kind: RobotKind) -> RobotNotifier {
+        let config: Robot = serde_json::from_value(json!({
+            "url": "https://oapi.dingtalk.com/robot/send?access_token=abc",
+            "secret": "SECxyz",
+        }))
+        .unwrap();
+        RobotNotifier::new("team".to_string(), kind, config).unwrap()
+    }
+
+    fn events() -> Vec<HealthInfo> {
+        vec![HealthInfo {
+            target: Target::Service("api".to_string(), None),
+            status: HealthStatus::Red,
+            alert: None,
+        }]
+    }
+
+    #[test]
+    fn dingtalk_signs_url() {
+        let (url, body) = robot(RobotKind::DingTalk).message(&events());
+        let query: std::collections::HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(query["access_token"], "abc");
+        let expected = hmac_sha256(
+            b"SECxyz",
+            format!("{}\nSECxyz", query["timestamp"]).as_bytes(),
+        );
+        assert_eq!(STANDARD.decode(&query["sign"]).unwrap(), expected);
+        assert_eq!(body["markdown"]["title"], "[Red] 资源监控预警");
+        assert!(body["markdown"]["text"]
+            .as_str()
+            .unwrap()
+            .contains("<font color=#d9534f>[Red]</font> service api"));
+    }
+
+    #[test]
+    fn feishu_signs_body() {
+        let (_, body) = robot(RobotKind::Feishu).message(&events());
+        let ts = body["timestamp"].as_str().unwrap();
+        let expected = hmac_sha256(format!("{}\nSECxyz", ts).as_bytes(), b"");
+        assert_eq!(body["sign"], STANDARD.encode(expected));
+        assert_eq!(body["card"]["header"]["template"], "red");
+    }
+
+    #[test]
+    fn wecom_rejects_secret() {
+        let config: Robot = serde_json::from_value(json!({
+            "url": "https://qyapi.weixin.qq.com/cgi-bin/webhook/send?key=abc",
+            "secret": "SECxyz",
+        }))
+        .unwrap();
+        let err = RobotNotifier::new("team".to_string(), RobotKind::WeCom, config)
+            .err()
+            .unwrap();
+        assert_eq!(err, "WeCom team: secret is not supported");
+    }
+
+    #[test]
+    fn errcode_is_checked() {
+        assert!(check_errcode(r#"{"errcode":0,"errmsg":"ok"}"#).is_ok());
+        assert!(check_errcode(r#"{"code":19021,"msg":"sign match fail"}"#).is_err());
+    }
+}
@@ -0,0 +1,197 @@
+use crate::config::model::AlertMatcher;
+use crate::core::dispatch::Matcher;
+use crate::core::ent::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::PathBuf,
+    sync::{Arc, Mutex, RwLock},
+};
+use uuid::Uuid;
+
+// 静默规则 生效期间匹配的目标仍记录状态 但不发送通知
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Silence {
+    pub id: String,
+    pub matchers: AlertMatcher,
+    pub starts_at: u64,
+    pub ends_at: u64,
+    pub created_by: String,
+    pub comment: String,
+    pub created_at: u64,
+}
+
+// 创建静默的请求 starts_at未填时立即生效
+#[derive(Debug, Deserialize)]
+pub struct NewSilence {
+    pub matchers: AlertMatcher,
+    pub starts_at: Option<u64>,
+    pub ends_at: u64,
+    pub created_by: String,
+    #[serde(default)]
+    pub comment: String,
+}
+
+impl Silence {
+    pub fn active(&self, now: u64) -> bool {
+        self.starts_at <= now && now < self.ends_at
+    }
+}
+
+// API与Logger共享的静默规则
+pub type Silences = Arc<RwLock<SilenceStore>>;
+
+// 静默规则保存在json文件中 重启后继续生效 过期的规则在下次写入时清理
+#[derive(Debug)]
+pub struct SilenceStore {
+    path: PathBuf,
+    silences: Vec<(Silence, Matcher)>,
+    version: u64,
+    saved: Arc<Mutex<u64>>, // 已写入文件的版本
+}
+
+// 修改后的静默规则 释放锁之后再写入文件
+#[derive(Debug)]
+pub struct Snapshot {
+    path: PathBuf,
+    silences: Vec<Silence>,
+    version: u64,
+    saved: Arc<Mutex<u64>>,
+}
+
+impl Snapshot {
+    // 先写临时文件再改名 避免进程中断留下半个文件
+    // 并发写入时跳过比文件中更旧的版本
+    pub fn write(&self) -> Result<(), String> {
+        let mut saved = self.saved.lock().unwrap();
+        if *saved >= self.version {
+            return Ok(());
+        }
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(&self.silences).unwrap())
+            .and_then(|_| fs::rename(&tmp, &self.path))
+            .map_err(|e| format!("save silences to {:?} fail: {}", self.path, e))?;
+        *saved = self.version;
+        Ok(())
+    }
+    pub async fn save(self) -> Result<(), String> {
+        tokio::task::spawn_blocking(move || self.write())
+            .await
+            .map_err(|e| format!("save silences fail: {}", e))?
+    }
+}
+
+impl SilenceStore {
+    // 文件不存在时视为没有静默规则
+    pub fn open(path: impl Into<PathBuf>) -> Result<SilenceStore, String> {
+        let path = path.into();
+        let silences: Vec<Silence> = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|e| format!("bad silences file {:?}: {}", path, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(format!("read silences file {:?} fail: {}", path, e)),
+        };
+        let silences = silences
+            .into_iter()
+            .map(|s| Matcher::new(s.matchers.clone()).map(|m| (s, m)))
+            .collect::<Result<_, _>>()?;
+        Ok(SilenceStore {
+            path,
+            silences,
+            version: 0,
+            saved: Arc::default(),
+        })
+    }
+    pub fn list(&self) -> Vec<Silence> {
+        self.silences.iter().map(|(s, _)| s.clone()).collect()
+    }
+    // 返回新建的规则及待写入文件的快照
+    pub fn add(&mut self, new: NewSilence, now: u64) -> Result<(Silence, Snapshot), String> {
+        let matcher = Matcher::new(new.matchers.clone())?;
+        let starts_at = new.starts_at.unwrap_or(now);
+        if new.ends_at <= starts_at {
+            return Err("ends_at must be after starts_at".to_string());
+        }
+        if new.ends_at <= now {
+            return Err("ends_at is in the past".to_string());
+        }
+        let silence = Silence {
+            id: Uuid::new_v4().to_string(),
+            matchers: new.matchers,
+            starts_at,
+            ends_at: new.ends_at,
+            created_by: new.created_by,
+            comment: new.comment,
+            created_at: now,
+        };
+        self.silences.retain(|(s, _)| s.ends_at > now);
+        self.silences.push((silence.clone(), matcher));
+        Ok((silence, self.snapshot()))
+    }
+    // 规则不存在时返回None
+    pub fn remove(&mut self, id: &str) -> Option<Snapshot> {
+        let len = self.silences.len();
+        self.silences.retain(|(s, _)| s.id != id);
+        if self.silences.len() == len {
+            return None;
+        }
+        Some(self.snapshot())
+    }
+    pub fn silenced(&self, info: &HealthInfo, now: u64) -> bool {
+        self.silences
+            .iter()
+            .any(|(s, m)| s.active(now) && m.matches(info))
+    }
+    fn snapshot(&mut self) -> Snapshot {
+        self.version += 1;
+        Snapshot {
+            path: self.path.clone(),
+            silences: self.list(),
+            version: self.version,
+            saved: self.saved.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str) -> HealthInfo {
+        HealthInfo {
+            target: Target::Node(id.to_string(), None),
+            status: HealthStatus::Red,
+            alert: None,
+        }
+    }
+
+    #[test]
+    fn silences_match_and_persist() {
+        let path = std::env::temp_dir().join(format!("silences-{}.json", Uuid::new_v4()));
+        let mut store = SilenceStore::open(&path).unwrap();
+        let new: NewSilence = serde_json::from_value(serde_json::json!({
+            "matchers": {"kind": "node", "name": "db-*"},
+            "starts_at": 100,
+            "ends_at": 200,
+            "created_by": "ops",
+            "comment": "disk migration",
+        }))
+        .unwrap();
+        let (silence, snapshot) = store.add(new, 50).unwrap();
+        snapshot.write().unwrap();
+        assert!(!store.silenced(&node("db-1"), 50));
+        assert!(store.silenced(&node("db-1"), 150));
+        assert!(!store.silenced(&node("web-1"), 150));
+        assert!(!store.silenced(&node("db-1"), 200));
+
+        // 重启后仍然生效
+        let mut store = SilenceStore::open(&path).unwrap();
+        assert!(store.silenced(&node("db-1"), 150));
+        store.remove(&silence.id).unwrap().write().unwrap();
+        assert!(store.remove(&silence.id).is_none());
+        assert!(!SilenceStore::open(&path)
+            .unwrap()
+            .silenced(&node("db-1"), 150));
+        fs::remove_file(path).unwrap();
+    }
+}
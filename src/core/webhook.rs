@@ -0,0 +1,260 @@
+use crate::config::model::Webhook;
+use crate::core::ent::HealthInfo;
+use crate::core::notifier::Notifier;
+use crate::core::render::RenderContext;
+use chrono::Utc;
+use handlebars::Handlebars;
+use hmac::{Hmac, Mac};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE},
+    Client, Request,
+};
+use serde::Serialize;
+use sha2::Sha256;
+use std::time::Duration;
+use tokio::time;
+
+// 默认的请求体
+#[derive(Serialize)]
+struct Payload<'a> {
+    update_time: String,
+    alerts: &'a [HealthInfo],
+}
+
+pub fn hmac_sha256(secret: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("hmac accepts any key size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+pub fn http_client(timeout_secs: u64) -> Client {
+    Client::builder()
+        .timeout(Duration::from_secs(timeout_secs))
+        .build()
+        .unwrap()
+}
+
+// 校验响应体 部分机器人接口出错时也返回200
+pub type Verify = fn(&str) -> Result<(), String>;
+
+pub fn accept_any(_: &str) -> Result<(), String> {
+    Ok(())
+}
+
+// 发送请求 失败(网络错误、非2xx或校验不通过)时按1s 2s 4s...退避重试
+pub async fn deliver(
+    client: Client,
+    name: String,
+    request: Request,
+    retries: u32,
+    verify: Verify,
+) -> bool {
+    let mut attempt = 0;
+    loop {
+        let req = request.try_clone().expect("request body is not a stream");
+        let err = match client.execute(req).await {
+            Ok(resp) if resp.status().is_success() => {
+                match resp
+                    .text()
+                    .await
+                    .map_err(|e| e.to_string())
+                    .and_then(|b| verify(&b))
+                {
+                    Ok(_) => {
+                        tracing::info!("{}: delivered", name);
+                        return true;
+                    }
+                    Err(e) => e,
+                }
+            }
+            Ok(resp) => format!("status {}", resp.status()),
+            Err(e) => e.to_string(),
+        };
+        if attempt >= retries {
+            tracing::error!("{}: give up after {} attempts: {}", name, attempt + 1, err);
+            return false;
+        }
+        tracing::warn!("{}: attempt {} failed: {}", name, attempt + 1, err);
+        time::sleep(Duration::from_secs(1 << attempt.min(6))).await;
+        attempt += 1;
+    }
+}
+
+// 通用的http回调渠道 事件以json形式POST到指定地址
+pub struct WebhookNotifier {
+    name: String,
+    url: String,
+    headers: HeaderMap,
+    template: Option<Handlebars<'static>>,
+    secret: Option<String>,
+    signature_header: String,
+    retries: u32,
+    client: Client,
+}
+
+impl WebhookNotifier {
+    pub fn new(name: String, config: Webhook) -> Result<WebhookNotifier, String> {
+        let err = |e: String| format!("webhook {}: {}", name, e);
+        reqwest::Url::parse(&config.url).map_err(|e| err(format!("bad url: {}", e)))?;
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        for (k, v) in &config.headers {
+            let key =
+                HeaderName::try_from(k).map_err(|e| err(format!("bad header {}: {}", k, e)))?;
+            let value =
+                HeaderValue::try_from(v).map_err(|e| err(format!("bad header {}: {}", k, e)))?;
+            headers.insert(key, value);
+        }
+        HeaderName::try_from(&config.signature_header)
+            .map_err(|e| err(format!("bad signature header: {}", e)))?;
+        let template = match config.body_template {
+            Some(source) => {
+                let mut hb = Handlebars::new();
+                hb.register_escape_fn(handlebars::no_escape);
+                hb.register_template_string("body", source)
+                    .map_err(|e| err(format!("bad body template: {}", e)))?;
+                Some(hb)
+            }
+            None => None,
+        };
+        Ok(WebhookNotifier {
+            name,
+            url: config.url,
+            headers,
+            template,
+            secret: config.secret,
+            signature_header: config.signature_header,
+            retries: config.retries,
+            client: http_client(config.timeout_secs),
+        })
+    }
+    // 模板渲染失败时返回None 不发送
+    fn body(&self, events: &[HealthInfo]) -> Option<String> {
+        match &self.template {
+            Some(hb) => hb
+                .render("body", &RenderContext::new(events))
+                .map_err(|e| tracing::error!("webhook {}: render body fail: {}", self.name, e))
+                .ok(),
+            None => Some(
+                serde_json::to_string(&Payload {
+                    update_time: Utc::now().to_rfc3339(),
+                    alerts: events,
+                })
+                .unwrap(),
+            ),
+        }
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn notify(&self, events: Vec<HealthInfo>) {
+        let Some(body) = self.body(&events) else {
+            return;
+        };
+        let mut builder = self.client.post(&self.url).headers(self.headers.clone());
+        // 对请求体做HMAC-SHA256签名 接收方可据此校验来源
+        if let Some(secret) = &self.secret {
+            let sign = hmac_sha256(secret.as_bytes(), body.as_bytes());
+            builder = builder.header(
+                self.signature_header.as_str(),
+                format!("sha256={}", hex::encode(sign)),
+            );
+        }
+        match builder.body(body).build() {
+            Ok(request) => {
+                tokio::spawn(deliver(
+                    self.client.clone(),
+                    format!("webhook {}", self.name),
+                    request,
+                    self.retries,
+                    accept_any,
+                ));
+            }
+            Err(e) => tracing::error!("webhook {}: build request fail: {}", self.name, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ent::*;
+    use axum::{extract::State, http::HeaderMap as Headers, http::StatusCode, routing::post};
+    use std::sync::{Arc, Mutex};
+
+    type Hits = Arc<Mutex<Vec<(Headers, String)>>>;
+
+    // 第一次返回500 之后返回200
+    async fn hook(State(hits): State<Hits>, headers: Headers, body: String) -> StatusCode {
+        let mut hits = hits.lock().unwrap();
+        hits.push((headers, body));
+        if hits.len() == 1 {
+            StatusCode::INTERNAL_SERVER_ERROR
+        } else {
+            StatusCode::OK
+        }
+    }
+
+    #[tokio::test]
+    async fn signs_body_and_retries() {
+        let hits: Hits = Arc::default();
+        let app = axum::Router::new()
+            .route("/hook", post(hook))
+            .with_state(hits.clone());
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let config: Webhook = serde_json::from_value(serde_json::json!({
+            "url": format!("http://{}/hook", addr),
+            "headers": {"X-Team": "ops"},
+            "body_template": "{{#each events}}{{name}}={{status}};{{/each}}",
+            "secret": "s3cret",
+            "retries": 2,
+        }))
+        .unwrap();
+        let notifier = WebhookNotifier::new("hook".to_string(), config).unwrap();
+        let events = vec![HealthInfo {
+            target: Target::Service("api".to_string(), None),
+            status: HealthStatus::Red,
+            alert: None,
+        }];
+        notifier.notify(events);
+        for _ in 0..50 {
+            if hits.lock().unwrap().len() >= 2 {
+                break;
+            }
+            time::sleep(Duration::from_millis(100)).await;
+        }
+        let hits = hits.lock().unwrap();
+        assert_eq!(hits.len(), 2);
+        let (headers, body) = &hits[1];
+        assert_eq!(body, "api=Red;");
+        assert_eq!(headers["x-team"], "ops");
+        let expected = format!(
+            "sha256={}",
+            hex::encode(hmac_sha256(b"s3cret", body.as_bytes()))
+        );
+        assert_eq!(headers["x-signature-256"], expected.as_str());
+    }
+
+    #[test]
+    fn render_failure_yields_no_body() {
+        let config: Webhook = serde_json::from_value(serde_json::json!({
+            "url": "http://127.0.0.1/hook",
+            "body_template": "{{#each events}}{{lookup this}}{{/each}}",
+        }))
+        .unwrap();
+        let notifier = WebhookNotifier::new("hook".to_string(), config).unwrap();
+        let events = vec![HealthInfo {
+            target: Target::Service("api".to_string(), None),
+            status: HealthStatus::Red,
+            alert: None,
+        }];
+        assert!(notifier.body(&events).is_none());
+    }
+}
@@ -0,0 +1,55 @@
+use crate::config::model::Metric;
+use std::collections::{HashMap, VecDeque};
+
+// 单个节点各指标的采样窗口
+pub type NodeWindows = HashMap<Metric, SampleWindow>;
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    ts: u64,
+    level: u8,
+}
+
+// 单条规则的短期采样窗口 记录每次采样命中的级别以及当前保持的级别
+#[derive(Debug, Default)]
+pub struct SampleWindow {
+    samples: VecDeque<Sample>,
+    held: u8,
+}
+
+impl SampleWindow {
+    // 同一时间戳的采样视为同一次上报 只更新不追加
+    // 去掉最早的采样后仍满足持续时长和次数时才丢弃 判断结果不受影响
+    pub fn push(&mut self, ts: u64, level: u8, for_secs: u64, for_samples: u32) {
+        match self.samples.back_mut() {
+            Some(last) if last.ts == ts => last.level = level,
+            _ => self.samples.push_back(Sample { ts, level }),
+        }
+        while self.samples.len() > for_samples.max(1) as usize
+            && ts.saturating_sub(self.samples[1].ts) >= for_secs
+        {
+            self.samples.pop_front();
+        }
+    }
+    // 最近连续命中level及以上的采样是否达到持续时长和次数
+    pub fn sustained(&self, level: u8, for_secs: u64, for_samples: u32) -> bool {
+        let Some(last) = self.samples.back() else {
+            return false;
+        };
+        let mut count = 0;
+        let mut since = last.ts;
+        for s in self.samples.iter().rev().take_while(|s| s.level >= level) {
+            count += 1;
+            since = s.ts;
+        }
+        count > 0
+            && count >= for_samples.max(1) as usize
+            && last.ts.saturating_sub(since) >= for_secs
+    }
+    pub fn held(&self) -> u8 {
+        self.held
+    }
+    pub fn hold(&mut self, level: u8) {
+        self.held = level;
+    }
+}
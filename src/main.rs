@@ -4,8 +4,22 @@
 //!
 //! - `GET /nodes`: return a JSON list of nodes.
 //! - `POST /nodes`: create a new Node.
+//! - `GET /nodes/:id`: return a specific Node.
 //! - `PATCH /nodes/:id`: update a specific Node.
 //! - `DELETE /nodes/:id`: delete a specific Node.
+//! - `GET /nodes/:id/thresholds`: return the threshold rules in effect for a Node.
+//! - `PUT /nodes/:id/thresholds`: override threshold rules for a Node at runtime.
+//! - `DELETE /nodes/:id/thresholds`: drop the runtime overrides of a Node.
+//! - `GET /services`: return the latest check result of every service.
+//! - `GET /services/:name`: return the latest check result of a service.
+//! - `GET /silences`: return all silences.
+//! - `POST /silences`: create a silence muting notifications of matching targets.
+//! - `DELETE /silences/:id`: delete a silence.
+//! - `GET /alerts`: return pending/firing alerts with their ack state, filterable by `severity`, `kind` and `target`.
+//! - `GET /alerts/history`: return recent alert state transitions, newest first.
+//! - `POST /alerts/:id/ack`: acknowledge a firing alert, stopping repeat notifications.
+//! - `GET /alerts/:id/ack?sig=`: the signed ack link embedded in notifications; shows a confirmation page.
+//! - `POST /alerts/:id/ack/confirm`: the confirmation form, acknowledging the alert as the submitted `by`.
 //!
 //! Run with
 //!
@@ -16,7 +30,7 @@
 mod config;
 mod core;
 
-use crate::config::model::load_bootstrap_config;
+use crate::config::load_bootstrap_config;
 use crate::core::*;
 // use lazy_static::lazy_static;
 use tokio::sync::{broadcast, mpsc};
@@ -31,7 +45,7 @@ use tokio::{signal, time};
 use axum::{
     error_handling::HandleErrorLayer,
     http::StatusCode,
-    routing::{delete, get},
+    routing::{delete, get, post},
     Router,
 };
 use tower_http::trace::TraceLayer;
@@ -58,12 +72,42 @@ async fn main() {
 
     //1. 初始化配置
     let config = load_bootstrap_config().unwrap();
+    let notifiers = build_notifiers(
+        config.smtp,
+        config.notifiers,
+        config.server.public_url.clone(),
+    )
+    .unwrap_or_else(|e| panic!("invalid notifier config:\n{}", e));
+    let dispatcher = Dispatcher::new(
+        notifiers,
+        config.route,
+        config.oncall,
+        config.alerting.renotify_secs,
+    )
+    .unwrap_or_else(|e| panic!("invalid route config:\n{}", e));
+    let silences = Arc::new(RwLock::new(
+        SilenceStore::open(&config.alerting.silences_file).unwrap(),
+    ));
+    let silences1 = silences.clone();
+    let signer = AckSigner::new(config.server.public_url.clone(), config.alerting.ack_secret);
+    let alerts = Arc::new(RwLock::new(AlertBook::new(
+        signer.clone(),
+        config.alerting.ack_timeout_secs,
+        config.alerting.flapping,
+    )));
+    let alerts1 = alerts.clone();
+    let maintenance = MaintenanceWindows::new(config.maintenance)
+        .unwrap_or_else(|e| panic!("invalid maintenance config:\n{}", e));
     //2. 生成医生
-    let dc = Doctor::new();
+    let dc = Doctor::new(config.rules, config.overrides)
+        .unwrap_or_else(|e| panic!("invalid override config:\n{}", e));
     let dc1 = dc.clone();
     let dc2 = dc.clone();
     // 节点监听服务用的channel
     let (in_pipe, mut out_pipe) = mpsc::channel(32);
+    // 服务检查结果 由巡检任务写入 API读取
+    let services = Arc::new(RwLock::new(HashMap::new()));
+    let services1 = services.clone();
     let in_1 = in_pipe.clone();
     let in_2 = in_pipe.clone();
     // 退出通知
@@ -74,20 +118,14 @@ async fn main() {
     //3. 启动用于监听节点状态和服务状态的任务
     tokio::spawn(async move {
         // Init Monitor
-        let mut logger = Logger::new(
-            dc1,
-            alarm::Alarm::new(
-                config.smtp.from,
-                config.smtp.to,
-                config.smtp.username,
-                config.smtp.password,
-                config.smtp.domain,
-            ),
-        );
+        let mut logger = Logger::new(dc1, dispatcher, alerts1, silences1, maintenance);
+        // 告警分组按秒检查是否到了发送时间
+        let mut tick = time::interval(time::Duration::from_secs(1));
         tracing::info!("begin nodes watch");
         loop {
             tokio::select! {
                 Some(event)=out_pipe.recv()=> logger.log(event),
+                _= tick.tick()=> logger.tick(),
                 _= shutdown_rx.recv()=>{break},
                 else => { break }
             };
@@ -96,9 +134,10 @@ async fn main() {
     });
     //4. 启动用于轮询各服务Health接口的任务
     //   同时此任务负责定时通知Monitor遍历节点以检查有哪些节点超时未更新
+    let srv_caller = ServiceChecker::new(dc2, in_1, config.services, services1)
+        .unwrap_or_else(|e| panic!("invalid service config:\n{}", e));
     tokio::spawn(async move {
         let mut check_count = 0;
-        let srv_caller = ServiceChecker::new(dc2, in_1, config.services);
         tracing::info!("begin service watch");
         loop {
             time::sleep(time::Duration::from_secs(300)).await;
@@ -122,13 +161,31 @@ async fn main() {
     //5. 启动监听节点健康状况的服务
     let app_state = Arc::new(AppState {
         db: RwLock::new(HashMap::new()),
+        services,
+        silences,
+        alerts,
+        signer,
         tx: in_pipe,
         dc,
     });
     // Compose the routes
     let app = Router::new()
         .route("/nodes", get(nodes_index).post(node_upsert))
-        .route("/nodes/:id", delete(node_delete))
+        .route("/nodes/:id", get(node_show).delete(node_delete))
+        .route(
+            "/nodes/:id/thresholds",
+            get(node_thresholds)
+                .put(node_thresholds_update)
+                .delete(node_thresholds_delete),
+        )
+        .route("/services", get(services_index))
+        .route("/services/:name", get(service_show))
+        .route("/silences", get(silences_index).post(silence_create))
+        .route("/silences/:id", delete(silence_delete))
+        .route("/alerts", get(alerts_index))
+        .route("/alerts/history", get(alerts_history))
+        .route("/alerts/:id/ack", get(alert_ack_link).post(alert_ack))
+        .route("/alerts/:id/ack/confirm", post(alert_ack_confirm))
         // Add middleware to all routes
         .layer(
             ServiceBuilder::new()